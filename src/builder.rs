@@ -0,0 +1,248 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, TcpListener, TcpStream, UdpSocket};
+
+use IntoInner;
+use select::{self, AddrSelection};
+use socket::{Socket, Template, Templated};
+use sockaddr::{SockAddr, ToSockAddr};
+
+/// An "in progress" socket of an arbitrary domain, type and protocol which has
+/// not yet been connected, bound or listened.
+///
+/// This is a more general version of `TcpBuilder` and `UdpBuilder` for when
+/// the arguments to the socket(2) call need to be specified explicitly. The
+/// same configuration methods are available, and the socket can be finished
+/// into a `TcpStream`, `TcpListener` or `UdpSocket`, or extracted as a raw
+/// socket with `into_socket`.
+pub struct SocketBuilder {
+    socket: RefCell<Option<Socket>>,
+    template: RefCell<Template>,
+}
+
+impl SocketBuilder {
+    /// Constructs a new SocketBuilder with the specified domain, type and
+    /// protocol.
+    ///
+    /// These arguments are passed straight through to the socket(2) function
+    /// on Unix and the WSASocket function on Windows, so any of the `AF_*`,
+    /// `SOCK_*` and `IPPROTO_*` constants that the platform supports may be
+    /// used.
+    pub fn new(domain: i32, ty: i32, protocol: i32) -> io::Result<SocketBuilder> {
+        let sock = try!(Socket::new(domain, ty, protocol));
+        Ok(SocketBuilder {
+            socket: RefCell::new(Some(sock)),
+            template: RefCell::new(Template::new(domain, ty, protocol)),
+        })
+    }
+
+    /// Binds this socket to the specified address.
+    ///
+    /// This function directly corresponds to the bind(2) function on Windows
+    /// and Unix. If `addr` resolves to more than one address, the one of the
+    /// same family as this socket is used, as with
+    /// `AddrSelection::MatchFamily`.
+    pub fn bind<T>(&self, addr: T) -> io::Result<&SocketBuilder>
+        where T: ToSocketAddrs
+    {
+        self.bind_with(addr, AddrSelection::MatchFamily)
+    }
+
+    /// Binds this socket to the specified address, choosing among the
+    /// addresses it resolves to with `selection`.
+    pub fn bind_with<T>(&self, addr: T, selection: AddrSelection)
+                        -> io::Result<&SocketBuilder>
+        where T: ToSocketAddrs
+    {
+        let family = self.template.borrow().family();
+        let addr = try!(select::select(addr, selection, family));
+        self.bind_socket(&addr.into()).map(|()| self)
+    }
+
+    /// Binds this socket to the specified `SockAddr`.
+    ///
+    /// This is the same as `bind`, but takes an already-built socket address
+    /// rather than resolving one through `ToSocketAddrs`.
+    pub fn bind_addr<A: ToSockAddr + ?Sized>(&self, addr: &A)
+                                             -> io::Result<&SocketBuilder> {
+        let addr = try!(addr.to_sock_addr());
+        self.bind_socket(&addr).map(|()| self)
+    }
+
+    /// Mark a socket as ready to accept incoming connection requests using
+    /// accept()
+    ///
+    /// This function directly corresponds to the listen(2) function on Windows
+    /// and Unix.
+    ///
+    /// An error will be returned if `listen` or `connect` has already been
+    /// called on this builder.
+    pub fn listen(&self, backlog: i32) -> io::Result<TcpListener> {
+        self.with_socket(|sock| {
+            sock.listen(backlog)
+        }).and_then(|()| {
+            self.to_tcp_listener()
+        })
+    }
+
+    /// Initiate a connection on this socket to the specified address.
+    ///
+    /// This function directly corresponds to the connect(2) function on Windows
+    /// and Unix.
+    ///
+    /// If `addr` resolves to several addresses they are tried in turn, and
+    /// every attempt after the first is made on a new socket of the matching
+    /// family which has had the options set on this builder applied to it,
+    /// and is bound to the same address if this builder was bound. An
+    /// attempt fails with `Error::BindFamily` if that address has no
+    /// equivalent in the attempt's family.
+    ///
+    /// An error will be returned if `listen` or `connect` has already been
+    /// called on this builder. To make further connections with the same
+    /// configuration use `connect_new`.
+    pub fn connect<T>(&self, addr: T) -> io::Result<TcpStream>
+        where T: ToSocketAddrs
+    {
+        let addrs = try!(addr.to_socket_addrs());
+        self.connect_each(addrs, false)
+    }
+
+    /// Initiate a connection to the specified address on a new socket which
+    /// has had the options set on this builder applied to it.
+    ///
+    /// This is the same as [`TcpBuilder::connect_new`][other].
+    ///
+    /// [other]: struct.TcpBuilder.html#method.connect_new
+    pub fn connect_new<T>(&self, addr: T) -> io::Result<TcpStream>
+        where T: ToSocketAddrs
+    {
+        let addrs = try!(addr.to_socket_addrs());
+        self.connect_each(addrs, true)
+    }
+
+    /// Initiate a connection on this socket to the specified `SockAddr`.
+    ///
+    /// This is the same as `connect`, but takes an already-built socket
+    /// address rather than resolving one through `ToSocketAddrs`.
+    pub fn connect_addr<A: ToSockAddr + ?Sized>(&self, addr: &A)
+                                                -> io::Result<TcpStream> {
+        try!(self.check_unfinished());
+        let addr = self.template.borrow().map(&try!(addr.to_sock_addr()));
+        let sock = try!(self.take_socket(addr.family()));
+        try!(sock.connect(&addr));
+        Ok(sock.into_inner().into_tcp_stream())
+    }
+
+    /// Converts this builder into a `TcpStream`
+    ///
+    /// This function will consume the internal socket and return it re-wrapped
+    /// as a `TcpStream`. An error will be returned if the internal socket has
+    /// already been consumed from a successful call to `connect`, `listen`,
+    /// etc.
+    pub fn to_tcp_stream(&self) -> io::Result<TcpStream> {
+        self.socket.borrow_mut().take().map(|s| s.into_inner().into_tcp_stream())
+            .ok_or(::Error::SocketConsumed.into())
+    }
+
+    /// Converts this builder into a `TcpListener`
+    ///
+    /// This function will consume the internal socket and return it re-wrapped
+    /// as a `TcpListener`. An error will be returned if the internal socket has
+    /// already been consumed from a successful call to `connect`, `listen`,
+    /// etc.
+    pub fn to_tcp_listener(&self) -> io::Result<TcpListener> {
+        self.socket.borrow_mut().take()
+            .map(|s| s.into_inner().into_tcp_listener())
+            .ok_or(::Error::SocketConsumed.into())
+    }
+
+    /// Converts this builder into a `UdpSocket`
+    ///
+    /// This function will consume the internal socket and return it re-wrapped
+    /// as a `UdpSocket`. An error will be returned if the internal socket has
+    /// already been consumed from a successful call to `connect`, `listen`,
+    /// etc.
+    pub fn to_udp_socket(&self) -> io::Result<UdpSocket> {
+        self.socket.borrow_mut().take()
+            .map(|s| s.into_inner().into_udp_socket())
+            .ok_or(::Error::SocketConsumed.into())
+    }
+
+    /// Connects to each of `addrs` in turn, on this builder's socket and then
+    /// on new ones, or only on new ones if `fresh` is set.
+    fn connect_each<I>(&self, addrs: I, fresh: bool) -> io::Result<TcpStream>
+        where I: Iterator<Item=SocketAddr>
+    {
+        if !fresh {
+            try!(self.check_unfinished());
+        }
+        let mut stream = None;
+        try!(::error::connect_each(addrs, |addr| {
+            let addr = self.template.borrow().map(&SockAddr::from(*addr));
+            let sock = if fresh {
+                try!(self.template.borrow().create(addr.family()))
+            } else {
+                try!(self.take_socket(addr.family()))
+            };
+            try!(sock.connect(&addr));
+            stream = Some(sock.into_inner().into_tcp_stream());
+            Ok(())
+        }));
+        Ok(stream.unwrap())
+    }
+
+    fn with_socket<F>(&self, f: F) -> io::Result<()>
+        where F: FnOnce(&Socket) -> io::Result<()>
+    {
+        match *self.socket.borrow() {
+            Some(ref s) => f(s),
+            None => Err(::Error::BuilderFinished.into()),
+        }
+    }
+}
+
+impl fmt::Debug for SocketBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.socket.borrow() {
+            Some(ref s) => write!(f, "SocketBuilder {{ socket: {:?} }}", s),
+            None => write!(f, "SocketBuilder {{ socket: None }}"),
+        }
+    }
+}
+
+impl ::AsInner for SocketBuilder {
+    type Inner = RefCell<Option<Socket>>;
+    fn as_inner(&self) -> &RefCell<Option<Socket>> { &self.socket }
+}
+
+impl ::FromInner for SocketBuilder {
+    type Inner = Socket;
+    fn from_inner(sock: Socket) -> SocketBuilder {
+        let template = Template::for_socket(&sock);
+        SocketBuilder {
+            socket: RefCell::new(Some(sock)),
+            template: RefCell::new(template),
+        }
+    }
+}
+
+impl Templated for SocketBuilder {
+    fn socket(&self) -> &RefCell<Option<Socket>> { &self.socket }
+    fn template(&self) -> &RefCell<Template> { &self.template }
+}
+
+impl ::IntoInner for SocketBuilder {
+    type Inner = Option<Socket>;
+    fn into_inner(self) -> Option<Socket> { self.socket.into_inner() }
+}
@@ -0,0 +1,294 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+
+use sys;
+
+#[cfg(feature = "nightly")] use std::time::Duration;
+
+/// The errors reported by this crate itself, rather than directly by the
+/// operating system.
+///
+/// Methods in this crate return `io::Error`, and when they fail for one of
+/// these reasons the `io::Error` wraps an `Error`, which can be recovered
+/// with `io::Error::get_ref` and `downcast_ref`. An `Error` can also be
+/// converted into an `io::Error` with `From`.
+///
+/// Failures of socket option calls are wrapped in `Error::Os`, which names
+/// the option. The `io::Error` has the same kind as the operating system's
+/// error, and `Error::raw_os_error` returns its error code.
+#[derive(Debug)]
+pub enum Error {
+    /// The builder's socket has already been converted into a finished
+    /// socket, for example by `to_tcp_stream`.
+    SocketConsumed,
+
+    /// An operation was attempted on a builder whose socket has already been
+    /// finished by `listen`, `connect` or similar.
+    BuilderFinished,
+
+    /// An operation needed the socket of a builder created with
+    /// `TcpBuilder::new_deferred` before `bind` or `connect` had created it.
+    NotCreated,
+
+    /// An address passed to `bind` resolved to more than one socket address,
+    /// and the address selection policy did not pick between them.
+    ///
+    /// Contains the candidate addresses.
+    MultipleAddresses(Vec<SocketAddr>),
+
+    /// An address did not resolve to any socket addresses.
+    NoAddresses,
+
+    /// A connection could not be established within the allotted time.
+    TimedOut,
+
+    /// Every port in the range passed to `bind_port_range` was in use.
+    NoFreePort,
+
+    /// A call to the operating system failed.
+    ///
+    /// This is currently reported for socket option calls, where the name of
+    /// the option is otherwise lost.
+    Os {
+        /// The failing call, such as `setsockopt(IPPROTO_TCP, TCP_NODELAY)`.
+        op: String,
+        /// The error reported by the operating system.
+        error: io::Error,
+    },
+
+    /// A builder bound to an address was used to create a socket of another
+    /// family, such as for a connection attempt to an address of that
+    /// family, and the address has no equivalent in it.
+    BindFamily,
+
+    /// An address which should have been an IP address, such as that of a
+    /// peer accepted by a TCP listener, belonged to another family.
+    NotInet,
+
+    /// There were no listeners to accept from.
+    NoListeners,
+
+    /// More sockets were passed to a wait than the platform supports.
+    TooManySockets,
+
+    /// A message was received with more ancillary data, such as file
+    /// descriptors, than there was room for.
+    ControlTruncated,
+}
+
+impl Error {
+    /// Returns the operating system error code of `err`, whether it was
+    /// returned as is or wrapped in an `Error::Os`.
+    ///
+    /// This is `io::Error::raw_os_error` for errors from any method of this
+    /// crate.
+    pub fn raw_os_error(err: &io::Error) -> Option<i32> {
+        err.raw_os_error().or_else(|| {
+            match err.get_ref().and_then(|e| e.downcast_ref::<Error>()) {
+                Some(&Error::Os { ref error, .. }) => error.raw_os_error(),
+                _ => None,
+            }
+        })
+    }
+
+    fn message(&self) -> &'static str {
+        match *self {
+            Error::SocketConsumed => "socket has already been consumed",
+            Error::BuilderFinished => "builder has already finished its socket",
+            Error::NotCreated => {
+                "socket has not been created yet; call bind or connect first"
+            }
+            Error::MultipleAddresses(..) => "more than one address resolved",
+            Error::NoAddresses => "no socket addresses could be resolved",
+            Error::TimedOut => "connection timed out",
+            Error::NoFreePort => "every port in the range is in use",
+            Error::Os { .. } => "operating system call failed",
+            Error::BindFamily => {
+                "bound address has no equivalent in the socket's family"
+            }
+            Error::NotInet => "address is not an IP address",
+            Error::NoListeners => "no listeners to accept from",
+            Error::TooManySockets => "too many sockets to wait on",
+            Error::ControlTruncated => "ancillary data was truncated",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Os { ref op, ref error } => write!(f, "{}: {}", op, error),
+            Error::MultipleAddresses(ref addrs) => {
+                try!(f.write_str(self.message()));
+                for (i, addr) in addrs.iter().enumerate() {
+                    try!(write!(f, "{} {}", if i == 0 {":"} else {","}, addr));
+                }
+                Ok(())
+            }
+            _ => f.write_str(self.message()),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        let kind = match err {
+            Error::TimedOut => io::ErrorKind::TimedOut,
+            Error::NoFreePort => io::ErrorKind::AddrInUse,
+            Error::Os { ref error, .. } => error.kind(),
+            Error::BindFamily |
+            Error::NoListeners |
+            Error::TooManySockets => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+/// The error returned when connecting fails for every address a name
+/// resolved to.
+///
+/// Connecting methods in this crate return an `io::Error` whose kind is that
+/// of the last attempt's error (or `Other` if nothing resolved), and which
+/// wraps one of these listing every attempt. It can be recovered with
+/// `io::Error::get_ref` and `downcast_ref`:
+///
+/// ```no_run
+/// use net2::{ConnectError, TcpBuilder};
+///
+/// let err = TcpBuilder::new_v4().unwrap().connect("localhost:80").unwrap_err();
+/// if let Some(err) = err.get_ref().and_then(|e| e.downcast_ref::<ConnectError>()) {
+///     for attempt in err.attempts() {
+///         println!("{}: {}", attempt.addr(), attempt.error());
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ConnectError {
+    attempts: Vec<ConnectAttempt>,
+}
+
+/// A single failed connection attempt within a `ConnectError`.
+#[derive(Debug)]
+pub struct ConnectAttempt {
+    addr: SocketAddr,
+    error: io::Error,
+    elapsed_ms: u32,
+}
+
+impl ConnectError {
+    /// Returns each failed attempt, in the order they failed.
+    ///
+    /// This is empty if the name did not resolve to any addresses.
+    pub fn attempts(&self) -> &[ConnectAttempt] {
+        &self.attempts
+    }
+}
+
+impl ConnectAttempt {
+    /// Returns the address this attempt tried to connect to.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Returns the error this attempt failed with.
+    pub fn error(&self) -> &io::Error {
+        &self.error
+    }
+
+    /// Returns how long this attempt took to fail, in milliseconds.
+    pub fn elapsed_ms(&self) -> u32 {
+        self.elapsed_ms
+    }
+
+    /// Returns how long this attempt took to fail.
+    #[cfg(feature = "nightly")]
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_millis(self.elapsed_ms as u64)
+    }
+}
+
+impl fmt::Display for ConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.attempts.is_empty() {
+            return f.write_str("no socket addresses resolved")
+        }
+        try!(write!(f, "failed to connect to any of {} addresses",
+                    self.attempts.len()));
+        for (i, a) in self.attempts.iter().enumerate() {
+            try!(write!(f, "{} {}: {} ({}ms)", if i == 0 {":"} else {","},
+                        a.addr, a.error, a.elapsed_ms));
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ConnectError {
+    fn description(&self) -> &str {
+        "failed to connect to any address"
+    }
+}
+
+/// Collects the failures of connection attempts into a `ConnectError`.
+pub struct Attempts {
+    attempts: Vec<ConnectAttempt>,
+}
+
+impl Attempts {
+    pub fn new() -> Attempts {
+        Attempts { attempts: Vec::new() }
+    }
+
+    /// Records that the attempt to connect to `addr` begun at `start_ms`, as
+    /// returned by `sys::now_ms`, failed.
+    pub fn push(&mut self, addr: SocketAddr, start_ms: u64, error: io::Error) {
+        let ms = sys::now_ms().saturating_sub(start_ms);
+        self.attempts.push(ConnectAttempt {
+            addr: addr,
+            error: error,
+            elapsed_ms: if ms > u32::max_value() as u64 {u32::max_value()} else {ms as u32},
+        });
+    }
+
+    /// Converts the recorded failures into an `io::Error`.
+    pub fn into_error(self) -> io::Error {
+        let kind = self.attempts.last().map(|a| a.error.kind())
+                       .unwrap_or(io::ErrorKind::Other);
+        io::Error::new(kind, ConnectError { attempts: self.attempts })
+    }
+}
+
+/// Calls `connect` with each of `addrs` in turn until one succeeds, returning
+/// an error listing every failed attempt if none do.
+pub fn connect_each<I, F>(addrs: I, mut connect: F) -> io::Result<()>
+    where I: IntoIterator<Item=SocketAddr>,
+          F: FnMut(&SocketAddr) -> io::Result<()>
+{
+    let mut attempts = Attempts::new();
+    for addr in addrs {
+        let start = sys::now_ms();
+        match connect(&addr) {
+            Ok(()) => return Ok(()),
+            Err(e) => attempts.push(addr, start, e),
+        }
+    }
+    Err(attempts.into_error())
+}
@@ -13,13 +13,15 @@
 use std::io;
 use std::mem;
 use std::net::{TcpStream, TcpListener, UdpSocket, Ipv4Addr, Ipv6Addr};
+use std::net::SocketAddr;
 use std::net::ToSocketAddrs;
 
 use libc::{self, c_int, socklen_t, c_void, c_uint};
 
-use {TcpBuilder, UdpBuilder, FromInner};
+use {TcpBuilder, UdpBuilder, SocketBuilder, FromInner};
 use sys;
-use socket;
+use socket::{self, Templated};
+use sockaddr::{self, SockAddr, ToSockAddr};
 
 #[cfg(feature = "nightly")] use std::time::Duration;
 
@@ -35,12 +37,15 @@ use socket;
 #[cfg(target_os = "dragonfly")] const IPV6_MULTICAST_LOOP: c_int = 11;
 #[cfg(target_os = "openbsd")] const IPV6_MULTICAST_LOOP: c_int = 11;
 #[cfg(target_os = "windows")] const IPV6_MULTICAST_LOOP: c_int = 11;
-#[cfg(any(target_os = "linux", target_os = "android"))] const IPV6_V6ONLY: c_int = 26;
-#[cfg(any(target_os = "macos", target_os = "ios"))] const IPV6_V6ONLY: c_int = 27;
-#[cfg(target_os = "windows")] const IPV6_V6ONLY: c_int = 27;
-#[cfg(target_os = "freebsd")] const IPV6_V6ONLY: c_int = 27;
-#[cfg(target_os = "dragonfly")] const IPV6_V6ONLY: c_int = 27;
-#[cfg(target_os = "openbsd")] const IPV6_V6ONLY: c_int = 27;
+#[cfg(any(target_os = "linux", target_os = "android"))] pub const IPV6_V6ONLY: c_int = 26;
+#[cfg(any(target_os = "macos", target_os = "ios"))] pub const IPV6_V6ONLY: c_int = 27;
+#[cfg(target_os = "windows")] pub const IPV6_V6ONLY: c_int = 27;
+#[cfg(target_os = "freebsd")] pub const IPV6_V6ONLY: c_int = 27;
+#[cfg(target_os = "dragonfly")] pub const IPV6_V6ONLY: c_int = 27;
+#[cfg(target_os = "openbsd")] pub const IPV6_V6ONLY: c_int = 27;
+#[cfg(any(target_os = "linux", target_os = "android"))] pub const IP_BIND_ADDRESS_NO_PORT: c_int = 24;
+#[cfg(any(target_os = "linux", target_os = "android"))] pub const IP_FREEBIND: c_int = 15;
+#[cfg(any(target_os = "linux", target_os = "android"))] pub const IP_TRANSPARENT: c_int = 19;
 
 cfg_if! {
     if #[cfg(windows)] {
@@ -75,22 +80,115 @@ pub fn setopt<T: Copy>(sock: Socket, opt: c_int, val: c_int,
     unsafe {
         let payload = &payload as *const T as *const c_void;
         try!(::cvt(libc::setsockopt(sock, opt, val, payload,
-                                    mem::size_of::<T>() as socklen_t)));
+                                    mem::size_of::<T>() as socklen_t))
+                 .map_err(|e| sockopt_error("setsockopt", opt, val, e)));
         Ok(())
     }
 }
 
-fn getopt<T: Copy>(sock: Socket, opt: c_int, val: c_int) -> io::Result<T> {
+/// Sets an option whose value is an arbitrary string of bytes.
+pub fn setopt_bytes(sock: Socket, opt: c_int, val: c_int, payload: &[u8])
+                    -> io::Result<()> {
+    unsafe {
+        ::cvt(libc::setsockopt(sock, opt, val,
+                               payload.as_ptr() as *const c_void,
+                               payload.len() as socklen_t))
+            .map(|_| ())
+            .map_err(|e| sockopt_error("setsockopt", opt, val, e))
+    }
+}
+
+/// Gets an option whose value is a string of at most `buf.len()` bytes,
+/// returning its length.
+pub fn getopt_bytes(sock: Socket, opt: c_int, val: c_int, buf: &mut [u8])
+                    -> io::Result<usize> {
+    unsafe {
+        let mut len = buf.len() as socklen_t;
+        try!(::cvt(getsockopt(sock, opt, val, buf.as_mut_ptr() as *mut _,
+                              &mut len))
+                 .map_err(|e| sockopt_error("getsockopt", opt, val, e)));
+        Ok(len as usize)
+    }
+}
+
+pub fn getopt<T: Copy>(sock: Socket, opt: c_int, val: c_int) -> io::Result<T> {
     unsafe {
         let mut slot: T = mem::zeroed();
         let mut len = mem::size_of::<T>() as socklen_t;
         try!(::cvt(getsockopt(sock, opt, val, &mut slot as *mut _ as *mut _,
-                              &mut len)));
+                              &mut len))
+                 .map_err(|e| sockopt_error("getsockopt", opt, val, e)));
         assert_eq!(len as usize, mem::size_of::<T>());
         Ok(slot)
     }
 }
 
+/// Wraps the error from a socket option call, naming the option.
+pub fn sockopt_error(call: &str, opt: c_int, val: c_int, err: io::Error)
+                     -> io::Error {
+    let level = match opt {
+        libc::SOL_SOCKET => "SOL_SOCKET",
+        libc::IPPROTO_IP => "IPPROTO_IP",
+        libc::IPPROTO_IPV6 => "IPPROTO_IPV6",
+        libc::IPPROTO_TCP => "IPPROTO_TCP",
+        _ => "",
+    };
+    let level = if level.is_empty() {opt.to_string()} else {level.to_string()};
+    let name = sockopt_names().into_iter().find(|&(o, v, _)| (o, v) == (opt, val))
+                              .map(|(_, _, name)| name.to_string())
+                              .unwrap_or(val.to_string());
+    ::Error::Os {
+        op: format!("{}({}, {})", call, level, name),
+        error: err,
+    }.into()
+}
+
+fn sockopt_names() -> Vec<(c_int, c_int, &'static str)> {
+    let mut names = vec![
+        (libc::SOL_SOCKET, libc::SO_BROADCAST, "SO_BROADCAST"),
+        (libc::SOL_SOCKET, libc::SO_ERROR, "SO_ERROR"),
+        (libc::SOL_SOCKET, libc::SO_KEEPALIVE, "SO_KEEPALIVE"),
+        (libc::SOL_SOCKET, libc::SO_RCVBUF, "SO_RCVBUF"),
+        (libc::SOL_SOCKET, libc::SO_RCVTIMEO, "SO_RCVTIMEO"),
+        (libc::SOL_SOCKET, libc::SO_REUSEADDR, "SO_REUSEADDR"),
+        (libc::SOL_SOCKET, libc::SO_SNDBUF, "SO_SNDBUF"),
+        (libc::SOL_SOCKET, libc::SO_SNDTIMEO, "SO_SNDTIMEO"),
+        (libc::SOL_SOCKET, libc::SO_TYPE, "SO_TYPE"),
+        (libc::IPPROTO_IP, libc::IP_ADD_MEMBERSHIP, "IP_ADD_MEMBERSHIP"),
+        (libc::IPPROTO_IP, libc::IP_DROP_MEMBERSHIP, "IP_DROP_MEMBERSHIP"),
+        (libc::IPPROTO_IP, libc::IP_MULTICAST_LOOP, "IP_MULTICAST_LOOP"),
+        (libc::IPPROTO_IP, libc::IP_MULTICAST_TTL, "IP_MULTICAST_TTL"),
+        (libc::IPPROTO_IP, libc::IP_TTL, "IP_TTL"),
+        (libc::IPPROTO_IPV6, libc::IPV6_ADD_MEMBERSHIP, "IPV6_ADD_MEMBERSHIP"),
+        (libc::IPPROTO_IPV6, libc::IPV6_DROP_MEMBERSHIP, "IPV6_DROP_MEMBERSHIP"),
+        (libc::IPPROTO_IPV6, IPV6_MULTICAST_LOOP, "IPV6_MULTICAST_LOOP"),
+        (libc::IPPROTO_IPV6, IPV6_V6ONLY, "IPV6_V6ONLY"),
+        (libc::IPPROTO_TCP, libc::TCP_NODELAY, "TCP_NODELAY"),
+    ];
+    #[cfg(unix)]
+    names.push((libc::SOL_SOCKET, libc::SO_REUSEPORT, "SO_REUSEPORT"));
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    names.extend(vec![
+        (libc::SOL_SOCKET, 16, "SO_PASSCRED"),
+        (libc::SOL_SOCKET, 17, "SO_PEERCRED"),
+        (libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, "TCP_KEEPIDLE"),
+        (libc::SOL_SOCKET, 25, "SO_BINDTODEVICE"),
+        (libc::SOL_SOCKET, 62, "SO_BINDTOIFINDEX"),
+        (libc::IPPROTO_IP, IP_BIND_ADDRESS_NO_PORT, "IP_BIND_ADDRESS_NO_PORT"),
+        (libc::IPPROTO_IP, IP_FREEBIND, "IP_FREEBIND"),
+        (libc::IPPROTO_IPV6, 78, "IPV6_FREEBIND"),
+        (libc::IPPROTO_IP, IP_TRANSPARENT, "IP_TRANSPARENT"),
+        (libc::IPPROTO_IPV6, 75, "IPV6_TRANSPARENT"),
+        (libc::IPPROTO_IP, 20, "IP_RECVORIGDSTADDR"),
+        (libc::IPPROTO_IPV6, 74, "IPV6_RECVORIGDSTADDR"),
+        (libc::IPPROTO_IP, 80, "SO_ORIGINAL_DST"),
+        (libc::IPPROTO_IPV6, 80, "IP6T_SO_ORIGINAL_DST"),
+    ]);
+    #[cfg(any(target_os = "macos", target_os = "ios"))]
+    names.push((libc::IPPROTO_TCP, libc::TCP_KEEPALIVE, "TCP_KEEPALIVE"));
+    names
+}
+
 /// Extension methods for the standard [`TcpStream` type][link] in `std::net`.
 ///
 /// [link]: https://doc.rust-lang.org/std/net/struct.TcpStream.html
@@ -325,6 +423,24 @@ pub trait TcpListenerExt {
     ///
     /// [link]: trait.TcpStreamExt.html#tymethod.set_nonblocking
     fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+
+    /// Accepts a new incoming connection from this listener, in the manner of
+    /// the accept4(2) function.
+    ///
+    /// The returned stream is always close-on-exec (non-inheritable on
+    /// Windows), and is in nonblocking mode if `nonblocking` is true. On Linux
+    /// both are set atomically as part of accepting the connection, so the
+    /// socket cannot leak into a child process spawned concurrently.
+    fn accept4(&self, nonblocking: bool) -> io::Result<(TcpStream, SocketAddr)>;
+
+    /// Accepts a new incoming connection from this listener, converting an
+    /// IPv4-mapped IPv6 peer address such as `::ffff:10.0.0.1` back to the
+    /// IPv4 address `10.0.0.1`.
+    ///
+    /// This is useful with dual-stack listeners, such as those created by
+    /// `TcpBuilder::new_dual_stack`, so that IPv4 peers are reported the
+    /// same way regardless of which listener accepted them.
+    fn accept_unmapped(&self) -> io::Result<(TcpStream, SocketAddr)>;
 }
 
 /// Extension methods for the standard [`UdpSocket` type][link] in `std::net`.
@@ -533,6 +649,41 @@ pub trait UdpSocketExt {
     /// receive data from the specified address.
     fn connect<A: ToSocketAddrs>(&self, addr: A) -> io::Result<()>;
 
+    /// Connects this UDP socket to the given `SockAddr`.
+    ///
+    /// This is the same as `connect`, but takes an already-built socket
+    /// address rather than resolving one through `ToSocketAddrs`, so it can
+    /// also be used with datagram sockets of other families which have been
+    /// converted into a `UdpSocket`.
+    fn connect_addr<A: ToSockAddr + ?Sized>(&self, addr: &A) -> io::Result<()>;
+
+    /// Sends data on the socket to the given `SockAddr`.
+    ///
+    /// This is the same as `UdpSocket::send_to`, but takes an already-built
+    /// socket address rather than resolving one through `ToSocketAddrs`.
+    fn send_to_addr<A: ToSockAddr + ?Sized>(&self, buf: &[u8], addr: &A)
+                                            -> io::Result<usize>;
+
+    /// Receives data from the socket, returning the number of bytes read and
+    /// the address the data came from as a `SockAddr`.
+    fn recv_from_addr(&self, buf: &mut [u8]) -> io::Result<(usize, SockAddr)>;
+
+    /// Sends data on the socket to the given address, converting an IPv4
+    /// address to the IPv4-mapped IPv6 address `::ffff:a.b.c.d` if this is an
+    /// IPv6 socket.
+    ///
+    /// This allows IPv4 peers to be addressed the same way through
+    /// dual-stack sockets, such as those created by
+    /// `UdpBuilder::new_dual_stack`, as through IPv4 sockets. As with
+    /// `UdpSocket::send_to`, only the first address `addr` resolves to is
+    /// used.
+    fn send_to_mapped<A: ToSocketAddrs>(&self, buf: &[u8], addr: A)
+                                        -> io::Result<usize>;
+
+    /// Receives data from the socket, converting an IPv4-mapped IPv6 source
+    /// address such as `::ffff:10.0.0.1` back to the IPv4 address `10.0.0.1`.
+    fn recv_from_unmapped(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+
     /// Moves this UDP socket into or out of nonblocking mode.
     ///
     /// For more information about this option, see
@@ -729,7 +880,7 @@ impl TcpStreamExt for TcpStream {
 }
 
 #[cfg(unix)]
-fn ms2timeout(dur: Option<u32>) -> libc::timeval {
+pub fn ms2timeout(dur: Option<u32>) -> libc::timeval {
     // TODO: be more rigorous
     match dur {
         Some(d) => libc::timeval {
@@ -750,7 +901,7 @@ fn timeout2ms(dur: libc::timeval) -> Option<u32> {
 }
 
 #[cfg(windows)]
-fn ms2timeout(dur: Option<u32>) -> libc::DWORD {
+pub fn ms2timeout(dur: Option<u32>) -> libc::DWORD {
     dur.unwrap_or(0)
 }
 
@@ -769,15 +920,19 @@ fn ms2dur(ms: u32) -> Duration {
 }
 
 #[cfg(feature = "nightly")]
-fn dur2ms(dur: Duration) -> u32 {
-    (dur.as_secs() as u32 * 1000) + (dur.subsec_nanos() / 1_000_000)
+pub fn dur2ms(dur: Duration) -> u32 {
+    // Round up so a nonzero duration never becomes zero, which would mean
+    // "no timeout", and saturate rather than wrap for huge durations
+    let ms = dur.as_secs().saturating_mul(1000)
+                .saturating_add((dur.subsec_nanos() as u64 + 999_999) / 1_000_000);
+    if ms > u32::max_value() as u64 {u32::max_value()} else {ms as u32}
 }
 
 fn int2bool(n: c_int) -> bool {
     if n == 0 {false} else {true}
 }
 
-fn int2err(n: c_int) -> Option<io::Error> {
+pub fn int2err(n: c_int) -> Option<io::Error> {
     if n == 0 {
         None
     } else {
@@ -922,26 +1077,79 @@ impl UdpSocketExt for UdpSocket {
         do_connect(self.as_sock(), addr)
     }
 
+    fn connect_addr<A: ToSockAddr + ?Sized>(&self, addr: &A) -> io::Result<()> {
+        let addr = try!(addr.to_sock_addr());
+        ::cvt(unsafe {
+            libc::connect(self.as_sock(), addr.as_ptr(), addr.len())
+        }).map(|_| ())
+    }
+
+    fn send_to_addr<A: ToSockAddr + ?Sized>(&self, buf: &[u8], addr: &A)
+                                            -> io::Result<usize> {
+        let addr = try!(addr.to_sock_addr());
+        send_to(self.as_sock(), buf, &addr)
+    }
+
+    fn recv_from_addr(&self, buf: &mut [u8]) -> io::Result<(usize, SockAddr)> {
+        recv_from(self.as_sock(), buf)
+    }
+
+    fn send_to_mapped<A: ToSocketAddrs>(&self, buf: &[u8], addr: A)
+                                        -> io::Result<usize> {
+        let addr = try!(try!(addr.to_socket_addrs()).next()
+                            .ok_or(::Error::NoAddresses));
+        let addr = match try!(self.local_addr()) {
+            SocketAddr::V6(..) => sockaddr::map_v4(addr),
+            SocketAddr::V4(..) => addr,
+        };
+        self.send_to(buf, addr)
+    }
+
+    fn recv_from_unmapped(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.recv_from(buf).map(|(n, addr)| (n, sockaddr::unmap_v4(addr)))
+    }
+
     fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
         set_nonblocking(self.as_sock(), nonblocking)
     }
 }
 
 fn do_connect<A: ToSocketAddrs>(sock: Socket, addr: A) -> io::Result<()> {
-    let err = io::Error::new(io::ErrorKind::Other,
-                             "no socket addresses resolved");
     let addrs = try!(addr.to_socket_addrs());
     let sys = sys::Socket::from_inner(sock);
     let sock = socket::Socket::from_inner(sys);
-    let ret = addrs.fold(Err(err), |prev, addr| {
-        prev.or_else(|_| sock.connect(&addr))
+    let ret = ::error::connect_each(addrs, |addr| {
+        sock.connect(&(*addr).into())
     });
     mem::forget(sock);
     return ret
 }
 
+#[cfg(unix)] type buflen_t = libc::size_t;
+#[cfg(windows)] type buflen_t = c_int;
+
+pub fn send_to(sock: Socket, buf: &[u8], addr: &SockAddr) -> io::Result<usize> {
+    let n = try!(::cvt(unsafe {
+        libc::sendto(sock, buf.as_ptr() as *const c_void,
+                     buf.len() as buflen_t, 0, addr.as_ptr(), addr.len())
+    }));
+    Ok(n as usize)
+}
+
+pub fn recv_from(sock: Socket, buf: &mut [u8]) -> io::Result<(usize, SockAddr)> {
+    let mut n = 0;
+    let addr = try!(sockaddr::with_storage(|addr, len| {
+        n = try!(::cvt(unsafe {
+            libc::recvfrom(sock, buf.as_mut_ptr() as *mut c_void,
+                           buf.len() as buflen_t, 0, addr, len)
+        }));
+        Ok(())
+    }));
+    Ok((n as usize, addr))
+}
+
 #[cfg(unix)]
-fn set_nonblocking(sock: Socket, nonblocking: bool) -> io::Result<()> {
+pub fn set_nonblocking(sock: Socket, nonblocking: bool) -> io::Result<()> {
     use libc::funcs::bsd44::ioctl;
     let mut nonblocking = nonblocking as libc::c_ulong;
     ::cvt(unsafe {
@@ -950,14 +1158,14 @@ fn set_nonblocking(sock: Socket, nonblocking: bool) -> io::Result<()> {
 }
 
 #[cfg(windows)]
-fn set_nonblocking(sock: Socket, nonblocking: bool) -> io::Result<()> {
+pub fn set_nonblocking(sock: Socket, nonblocking: bool) -> io::Result<()> {
     let mut nonblocking = nonblocking as libc::c_ulong;
     ::cvt(unsafe {
         libc::ioctlsocket(sock, FIONBIO, &mut nonblocking)
     }).map(|_| ())
 }
 
-fn ip2in_addr(ip: &Ipv4Addr) -> libc::in_addr {
+pub fn ip2in_addr(ip: &Ipv4Addr) -> libc::in_addr {
     let oct = ip.octets();
     libc::in_addr {
         s_addr: ::hton(((oct[0] as u32) << 24) |
@@ -967,7 +1175,7 @@ fn ip2in_addr(ip: &Ipv4Addr) -> libc::in_addr {
     }
 }
 
-fn ip2in6_addr(ip: &Ipv6Addr) -> libc::in6_addr {
+pub fn ip2in6_addr(ip: &Ipv6Addr) -> libc::in6_addr {
     let seg = ip.segments();
     libc::in6_addr {
         s6_addr: [
@@ -1008,6 +1216,24 @@ impl TcpListenerExt for TcpListener {
     fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
         set_nonblocking(self.as_sock(), nonblocking)
     }
+
+    fn accept4(&self, nonblocking: bool) -> io::Result<(TcpStream, SocketAddr)> {
+        let listener = sys::Socket::from_inner(self.as_sock());
+        let mut sock = None;
+        let ret = sockaddr::with_storage(|addr, len| {
+            sock = Some(try!(listener.accept(addr, len, nonblocking)));
+            Ok(())
+        });
+        mem::forget(listener);
+        let addr = try!(ret);
+        let sock = sock.unwrap().into_tcp_stream();
+        let addr = try!(addr.as_socket_addr().ok_or(::Error::NotInet));
+        Ok((sock, addr))
+    }
+
+    fn accept_unmapped(&self) -> io::Result<(TcpStream, SocketAddr)> {
+        self.accept().map(|(s, addr)| (s, sockaddr::unmap_v4(addr)))
+    }
 }
 
 impl TcpBuilder {
@@ -1017,7 +1243,7 @@ impl TcpBuilder {
     ///
     /// [other]: trait.TcpStreamExt.html#tymethod.set_ttl
     pub fn ttl(&self, ttl: u32) -> io::Result<&Self> {
-        setopt(self.as_sock(), libc::IPPROTO_IP, libc::IP_TTL, ttl as c_int)
+        self.setopt(libc::IPPROTO_IP, libc::IP_TTL, ttl as c_int)
             .map(|()| self)
     }
 
@@ -1027,7 +1253,7 @@ impl TcpBuilder {
     ///
     /// [other]: trait.TcpStreamExt.html#tymethod.set_only_v6
     pub fn only_v6(&self, only_v6: bool) -> io::Result<&Self> {
-        setopt(self.as_sock(), libc::IPPROTO_IPV6, IPV6_V6ONLY, only_v6 as c_int)
+        self.setopt(libc::IPPROTO_IPV6, IPV6_V6ONLY, only_v6 as c_int)
             .map(|()| self)
     }
 
@@ -1037,8 +1263,8 @@ impl TcpBuilder {
     /// addresses. For IPv4 sockets this means that a socket may bind even when
     /// there's a socket already listening on this port.
     pub fn reuse_address(&self, reuse: bool) -> io::Result<&Self> {
-        setopt(self.as_sock(), libc::SOL_SOCKET, libc::SO_REUSEADDR,
-               reuse as c_int).map(|()| self)
+        self.setopt(libc::SOL_SOCKET, libc::SO_REUSEADDR, reuse as c_int)
+            .map(|()| self)
     }
 
     /// Get the value of the `SO_ERROR` option on this socket.
@@ -1046,8 +1272,13 @@ impl TcpBuilder {
     /// This will retrieve the stored error in the underlying socket, clearing
     /// the field in the process. This can be useful for checking errors between
     /// calls.
+    ///
+    /// An error is returned if the builder has no socket, either because it
+    /// was finished or because it was created with `new_deferred` and has not
+    /// yet been bound.
     pub fn take_error(&self) -> io::Result<Option<io::Error>> {
-        getopt(self.as_sock(), libc::SOL_SOCKET, libc::SO_ERROR).map(int2err)
+        let sock = try!(self.raw_socket());
+        getopt(sock, libc::SOL_SOCKET, libc::SO_ERROR).map(int2err)
     }
 }
 
@@ -1058,7 +1289,7 @@ impl UdpBuilder {
     ///
     /// [other]: trait.TcpStreamExt.html#tymethod.set_ttl
     pub fn ttl(&self, ttl: u32) -> io::Result<&Self> {
-        setopt(self.as_sock(), libc::IPPROTO_IP, libc::IP_TTL, ttl as c_int)
+        self.setopt(libc::IPPROTO_IP, libc::IP_TTL, ttl as c_int)
             .map(|()| self)
     }
 
@@ -1068,7 +1299,7 @@ impl UdpBuilder {
     ///
     /// [other]: struct.TcpBuilder.html#method.only_v6
     pub fn only_v6(&self, only_v6: bool) -> io::Result<&Self> {
-        setopt(self.as_sock(), libc::IPPROTO_IPV6, IPV6_V6ONLY, only_v6 as c_int)
+        self.setopt(libc::IPPROTO_IPV6, IPV6_V6ONLY, only_v6 as c_int)
             .map(|()| self)
     }
 
@@ -1078,8 +1309,8 @@ impl UdpBuilder {
     ///
     /// [other]: struct.TcpBuilder.html#method.reuse_address
     pub fn reuse_address(&self, reuse: bool) -> io::Result<&Self> {
-        setopt(self.as_sock(), libc::SOL_SOCKET, libc::SO_REUSEADDR,
-               reuse as c_int).map(|()| self)
+        self.setopt(libc::SOL_SOCKET, libc::SO_REUSEADDR, reuse as c_int)
+            .map(|()| self)
     }
 
     /// Get the value of the `SO_ERROR` option on this socket.
@@ -1088,6 +1319,51 @@ impl UdpBuilder {
     /// the field in the process. This can be useful for checking errors between
     /// calls.
     pub fn take_error(&self) -> io::Result<Option<io::Error>> {
-        getopt(self.as_sock(), libc::SOL_SOCKET, libc::SO_ERROR).map(int2err)
+        let sock = try!(self.raw_socket());
+        getopt(sock, libc::SOL_SOCKET, libc::SO_ERROR).map(int2err)
+    }
+}
+
+impl SocketBuilder {
+    /// Sets the value for the `IP_TTL` option on this socket.
+    ///
+    /// This is the same as [`TcpStreamExt::set_ttl`][other].
+    ///
+    /// [other]: trait.TcpStreamExt.html#tymethod.set_ttl
+    pub fn ttl(&self, ttl: u32) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IP, libc::IP_TTL, ttl as c_int)
+            .map(|()| self)
+    }
+
+    /// Sets the value for the `IPV6_V6ONLY` option on this socket.
+    ///
+    /// This is the same as [`TcpStreamExt::set_only_v6`][other].
+    ///
+    /// [other]: trait.TcpStreamExt.html#tymethod.set_only_v6
+    pub fn only_v6(&self, only_v6: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IPV6, IPV6_V6ONLY, only_v6 as c_int)
+            .map(|()| self)
+    }
+
+    /// Set value for the `SO_REUSEADDR` option on this socket.
+    ///
+    /// This is the same as [`TcpBuilder::reuse_address`][other].
+    ///
+    /// [other]: struct.TcpBuilder.html#method.reuse_address
+    pub fn reuse_address(&self, reuse: bool) -> io::Result<&Self> {
+        self.setopt(libc::SOL_SOCKET, libc::SO_REUSEADDR, reuse as c_int)
+            .map(|()| self)
+    }
+
+    /// Get the value of the `SO_ERROR` option on this socket.
+    ///
+    /// This will retrieve the stored error in the underlying socket, clearing
+    /// the field in the process. This can be useful for checking errors between
+    /// calls.
+    ///
+    /// An error is returned if the builder's socket has been finished.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        let sock = try!(self.raw_socket());
+        getopt(sock, libc::SOL_SOCKET, libc::SO_ERROR).map(int2err)
     }
 }
@@ -0,0 +1,243 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cmp;
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(feature = "nightly")] use std::time::Duration;
+
+use {AsInner, IntoInner};
+use error::Attempts;
+use sockaddr::SockAddr;
+use socket::{Socket, Templated};
+use sys;
+use tcp::TcpBuilder;
+
+/// A "Happy Eyeballs" (RFC 8305) connector for TCP streams.
+///
+/// Rather than trying each address a name resolves to one after another,
+/// waiting for each to fail, this interleaves the IPv6 and IPv4 addresses and
+/// starts a new connection attempt every `delay_ms` milliseconds (or as soon
+/// as the previous attempt fails) while the earlier attempts are still in
+/// progress. The first attempt to succeed is returned and the others are
+/// closed, so a host with a broken IPv6 route connects over IPv4 after a
+/// short delay instead of after a full connect timeout.
+///
+/// Each attempt is made on a fresh socket, configured either by a closure or
+/// from a `TcpBuilder` acting as a template, so the same options can be
+/// applied regardless of the address family.
+#[derive(Clone, Debug)]
+pub struct HappyEyeballs {
+    delay_ms: u32,
+    timeout_ms: Option<u32>,
+}
+
+impl Default for HappyEyeballs {
+    fn default() -> HappyEyeballs {
+        HappyEyeballs::new()
+    }
+}
+
+impl HappyEyeballs {
+    /// Creates a new connector with the attempt delay of 250ms recommended
+    /// by RFC 8305 and no overall timeout.
+    pub fn new() -> HappyEyeballs {
+        HappyEyeballs { delay_ms: 250, timeout_ms: None }
+    }
+
+    /// Sets how long to wait for an attempt to succeed before starting the
+    /// next one, in milliseconds.
+    pub fn delay_ms(&mut self, ms: u32) -> &mut HappyEyeballs {
+        self.delay_ms = ms;
+        self
+    }
+
+    /// Sets how long to wait for an attempt to succeed before starting the
+    /// next one.
+    ///
+    /// For more information, see [`delay_ms`][link].
+    ///
+    /// [link]: #method.delay_ms
+    #[cfg(feature = "nightly")]
+    pub fn delay(&mut self, delay: Duration) -> &mut HappyEyeballs {
+        self.delay_ms(::ext::dur2ms(delay))
+    }
+
+    /// Sets the overall time allowed for connecting, across all attempts, in
+    /// milliseconds.
+    ///
+    /// If no attempt has succeeded by then an error of kind `TimedOut` is
+    /// returned. A value of `None`, the default, means attempts are only
+    /// bounded by the operating system's own connect timeout.
+    pub fn timeout_ms(&mut self, ms: Option<u32>) -> &mut HappyEyeballs {
+        self.timeout_ms = ms;
+        self
+    }
+
+    /// Sets the overall time allowed for connecting, across all attempts.
+    ///
+    /// For more information, see [`timeout_ms`][link].
+    ///
+    /// [link]: #method.timeout_ms
+    #[cfg(feature = "nightly")]
+    pub fn timeout(&mut self, timeout: Option<Duration>) -> &mut HappyEyeballs {
+        self.timeout_ms(timeout.map(::ext::dur2ms))
+    }
+
+    /// Connects to the addresses `addr` resolves to, returning the first
+    /// stream to be established.
+    ///
+    /// `configure` is called with a new `TcpBuilder` of the matching family
+    /// before each attempt, and may set any options on it. An error returned
+    /// from it fails only that attempt. If every attempt fails, or the
+    /// timeout elapses first, the error wraps a `ConnectError` listing each
+    /// attempt.
+    pub fn connect<A, F>(&self, addr: A, mut configure: F) -> io::Result<TcpStream>
+        where A: ToSocketAddrs, F: FnMut(&TcpBuilder) -> io::Result<()>
+    {
+        self.dial(addr, |addr| {
+            let builder = match *addr {
+                SocketAddr::V4(..) => try!(TcpBuilder::new_v4()),
+                SocketAddr::V6(..) => try!(TcpBuilder::new_v6()),
+            };
+            try!(configure(&builder));
+            let sock = builder.as_inner().borrow_mut().take();
+            sock.ok_or_else(|| ::Error::SocketConsumed.into())
+        })
+    }
+
+    /// Connects to the addresses `addr` resolves to, using `builder` as a
+    /// template for each attempt, and returns the first stream to be
+    /// established.
+    ///
+    /// Each attempt's socket has the options set on `builder` applied to it,
+    /// as with `TcpBuilder::connect`, and the error when every attempt fails
+    /// is the same as for `connect`.
+    pub fn connect_builder<A>(&self, builder: &TcpBuilder, addr: A)
+                              -> io::Result<TcpStream>
+        where A: ToSocketAddrs
+    {
+        self.dial(addr, |addr| {
+            let addr = SockAddr::from(*addr);
+            builder.take_socket(addr.family())
+        })
+    }
+
+    fn dial<A, F>(&self, addr: A, mut new_socket: F) -> io::Result<TcpStream>
+        where A: ToSocketAddrs, F: FnMut(&SocketAddr) -> io::Result<Socket>
+    {
+        let addrs = interleave(try!(addr.to_socket_addrs()).collect());
+        let start = sys::now_ms();
+        let deadline = self.timeout_ms.map(|ms| start + ms as u64);
+
+        let mut next = 0;
+        let mut next_at = start;
+        let mut pending: Vec<(Socket, SocketAddr, u64)> = Vec::new();
+        let mut attempts = Attempts::new();
+        loop {
+            let now = sys::now_ms();
+            if deadline.map_or(false, |d| now >= d) {
+                for (_, addr, started) in pending {
+                    let err = ::Error::TimedOut.into();
+                    attempts.push(addr, started, err);
+                }
+                return Err(attempts.into_error())
+            }
+
+            if next < addrs.len() && (now >= next_at || pending.is_empty()) {
+                let addr = addrs[next];
+                next += 1;
+                next_at = now + self.delay_ms as u64;
+                match start_attempt(&addr, &mut new_socket) {
+                    Ok((sock, true)) => return finish(sock),
+                    Ok((sock, false)) => pending.push((sock, addr, now)),
+                    Err(e) => {
+                        // Failing without even connecting doesn't use up
+                        // the delay, so the next attempt starts at once
+                        attempts.push(addr, now, e);
+                        next_at = now;
+                    }
+                }
+                continue
+            }
+            if pending.is_empty() {
+                break
+            }
+
+            let mut wait = deadline.map(|d| d - now);
+            if next < addrs.len() {
+                let until_next = next_at.saturating_sub(now);
+                wait = Some(wait.map_or(until_next, |w| cmp::min(w, until_next)));
+            }
+            let ready = {
+                let socks = pending.iter().map(|p| p.0.as_inner())
+                                   .collect::<Vec<_>>();
+                try!(sys::wait_writable(&socks, wait.map(clamp_ms)))
+            };
+            for i in (0..pending.len()).rev() {
+                if !ready[i] {
+                    continue
+                }
+                let (sock, addr, started) = pending.remove(i);
+                match sock.connect_result() {
+                    Ok(()) => return finish(sock),
+                    Err(e) => {
+                        attempts.push(addr, started, e);
+                        next_at = sys::now_ms();
+                    }
+                }
+            }
+        }
+
+        Err(attempts.into_error())
+    }
+}
+
+/// Orders `addrs` by alternating address families, starting with the family
+/// of the first address, as described in section 4 of RFC 8305.
+fn interleave(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_v6 = match addrs.first() {
+        Some(&SocketAddr::V6(..)) => true,
+        _ => false,
+    };
+    let (mut first, mut second): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|a| {
+        match *a {
+            SocketAddr::V6(..) => first_v6,
+            SocketAddr::V4(..) => !first_v6,
+        }
+    });
+    first.reverse();
+    second.reverse();
+    let mut ret = Vec::with_capacity(first.len() + second.len());
+    loop {
+        match (first.pop(), second.pop()) {
+            (None, None) => return ret,
+            (a, b) => ret.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+fn start_attempt<F>(addr: &SocketAddr, new_socket: &mut F) -> io::Result<(Socket, bool)>
+    where F: FnMut(&SocketAddr) -> io::Result<Socket>
+{
+    let sock = try!(new_socket(addr));
+    try!(sock.set_nonblocking(true));
+    let done = try!(sock.start_connect(&(*addr).into()));
+    Ok((sock, done))
+}
+
+fn finish(sock: Socket) -> io::Result<TcpStream> {
+    try!(sock.set_nonblocking(false));
+    Ok(sock.into_inner().into_tcp_stream())
+}
+
+fn clamp_ms(ms: u64) -> u32 {
+    if ms > u32::max_value() as u64 {u32::max_value()} else {ms as u32}
+}
@@ -49,38 +49,34 @@ extern crate ws2_32;
 
 use std::io;
 use std::ops::Neg;
-use std::net::{ToSocketAddrs, SocketAddr};
 
 use utils::{One, NetInt};
 
+mod builder;
 mod tcp;
 mod udp;
 mod socket;
+mod sockaddr;
 mod ext;
+mod error;
+mod happy;
+mod listener;
+mod select;
 mod utils;
 
 #[cfg(unix)] #[path = "sys/unix/mod.rs"] mod sys;
 #[cfg(windows)] #[path = "sys/windows/mod.rs"] mod sys;
 #[cfg(unix)] pub mod unix;
 
+pub use builder::SocketBuilder;
 pub use tcp::TcpBuilder;
 pub use udp::UdpBuilder;
 pub use ext::{TcpStreamExt, TcpListenerExt, UdpSocketExt};
-
-fn one_addr<T: ToSocketAddrs>(tsa: T) -> io::Result<SocketAddr> {
-    let mut addrs = try!(tsa.to_socket_addrs());
-    let addr = match addrs.next() {
-        Some(addr) => addr,
-        None => return Err(io::Error::new(io::ErrorKind::Other,
-                                          "no socket addresses could be resolved"))
-    };
-    if addrs.next().is_none() {
-        Ok(addr)
-    } else {
-        Err(io::Error::new(io::ErrorKind::Other,
-                           "more than one address resolved"))
-    }
-}
+pub use sockaddr::{SockAddr, ToSockAddr};
+pub use happy::HappyEyeballs;
+pub use listener::ListenerSet;
+pub use select::AddrSelection;
+pub use error::{Error, ConnectError, ConnectAttempt};
 
 fn cvt<T: One + PartialEq + Neg<Output=T>>(t: T) -> io::Result<T> {
     let one: T = T::one();
@@ -101,6 +97,7 @@ fn cvt_win<T: PartialEq + utils::Zero>(t: T) -> io::Result<T> {
 }
 
 fn hton<I: NetInt>(i: I) -> I { i.to_be() }
+fn ntoh<I: NetInt>(i: I) -> I { <I as NetInt>::from_be(i) }
 
 trait AsInner {
     type Inner;
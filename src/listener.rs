@@ -0,0 +1,189 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::cell::Cell;
+use std::io;
+use std::iter::FromIterator;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use libc::{self, c_int};
+
+use {AsInner, IntoInner};
+use ext::{self, AsSock};
+use sockaddr::SockAddr;
+use socket::Templated;
+use sys;
+
+/// A group of TCP listeners which are accepted from together.
+///
+/// A set is usually created with `TcpBuilder::listen_all`, which creates one
+/// listener for each address a name resolves to, each with the options set
+/// on the builder, but listeners created in any other way may be added to it
+/// as well.
+///
+/// # Examples
+///
+/// ```no_run
+/// use net2::TcpBuilder;
+///
+/// let builder = TcpBuilder::new_v4().unwrap();
+/// builder.reuse_address(true).unwrap();
+///
+/// let addrs = ["0.0.0.0:8080".parse().unwrap(), "[::]:8080".parse().unwrap()];
+/// let mut set = builder.listen_all(&addrs[..], 128).unwrap();
+/// set.extend(builder.listen_all("127.0.0.1:9000", 16).unwrap());
+///
+/// loop {
+///     let (_stream, addr, i) = set.accept().unwrap();
+///     println!("{} connected to {:?}", addr, set.listeners()[i].local_addr());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ListenerSet {
+    listeners: Vec<TcpListener>,
+    next: Cell<usize>,
+}
+
+impl ListenerSet {
+    /// Creates an empty set of listeners.
+    pub fn new() -> ListenerSet {
+        ListenerSet { listeners: Vec::new(), next: Cell::new(0) }
+    }
+
+    /// Adds a listener to this set.
+    pub fn push(&mut self, listener: TcpListener) {
+        self.listeners.push(listener);
+    }
+
+    /// Returns the listeners in this set, in the order they were added.
+    ///
+    /// The index returned by `accept` is an index into this slice.
+    pub fn listeners(&self) -> &[TcpListener] {
+        &self.listeners
+    }
+
+    /// Returns the local address of each listener in this set.
+    pub fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.listeners.iter().map(|l| l.local_addr()).collect()
+    }
+
+    /// Accepts a new incoming connection on whichever listener in this set
+    /// receives one first.
+    ///
+    /// This blocks until one of the listeners is readable, and then accepts
+    /// from it, returning the stream, the address of the remote peer and the
+    /// index of the listener it was accepted on. When several listeners are
+    /// ready they take turns, so that a busy listener cannot starve the
+    /// others.
+    ///
+    /// The accept itself is done in nonblocking mode, so if another thread or
+    /// process sharing a listener takes the connection first this goes back
+    /// to waiting rather than blocking on that one listener. The listeners
+    /// are left in blocking mode afterwards, as is the returned stream.
+    pub fn accept(&self) -> io::Result<(TcpStream, SocketAddr, usize)> {
+        if self.listeners.is_empty() {
+            return Err(::Error::NoListeners.into())
+        }
+        let socks = self.listeners.iter().map(|l| l.as_sock())
+                                  .collect::<Vec<_>>();
+        let n = self.listeners.len();
+        loop {
+            let ready = try!(sys::wait_readable(&socks, None));
+            let start = self.next.get() % n;
+            let i = match (start..n).chain(0..start).find(|&i| ready[i]) {
+                Some(i) => i,
+                None => continue,
+            };
+            self.next.set(i + 1);
+            match accept_nonblocking(&self.listeners[i]) {
+                Ok((stream, addr)) => return Ok((stream, addr, i)),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Consumes this set, returning its listeners.
+    pub fn into_listeners(self) -> Vec<TcpListener> {
+        self.listeners
+    }
+}
+
+impl Default for ListenerSet {
+    fn default() -> ListenerSet {
+        ListenerSet::new()
+    }
+}
+
+impl Extend<TcpListener> for ListenerSet {
+    fn extend<I: IntoIterator<Item=TcpListener>>(&mut self, iter: I) {
+        self.listeners.extend(iter)
+    }
+}
+
+impl FromIterator<TcpListener> for ListenerSet {
+    fn from_iter<I: IntoIterator<Item=TcpListener>>(iter: I) -> ListenerSet {
+        let mut set = ListenerSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl IntoIterator for ListenerSet {
+    type Item = TcpListener;
+    type IntoIter = ::std::vec::IntoIter<TcpListener>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.listeners.into_iter()
+    }
+}
+
+fn accept_nonblocking(listener: &TcpListener)
+                      -> io::Result<(TcpStream, SocketAddr)> {
+    try!(ext::set_nonblocking(listener.as_sock(), true));
+    let res = listener.accept();
+    try!(ext::set_nonblocking(listener.as_sock(), false));
+    let (stream, addr) = try!(res);
+    // Some platforms have accepted sockets inherit the listener's mode
+    try!(ext::set_nonblocking(stream.as_sock(), false));
+    Ok((stream, addr))
+}
+
+/// Creates a listener from `builder`'s template for each address `addr`
+/// resolves to.
+///
+/// If the addresses include both IPv4 and IPv6 ones the IPv6 listeners are
+/// made `IPV6_V6ONLY`, unless the builder set that option itself, so that
+/// they do not conflict with the IPv4 listeners on the same port.
+pub fn listen_all<B, A>(builder: &B, addr: A, backlog: i32)
+                        -> io::Result<ListenerSet>
+    where B: Templated, A: ToSocketAddrs
+{
+    let addrs = try!(addr.to_socket_addrs()).collect::<Vec<_>>();
+    if addrs.is_empty() {
+        return Err(::Error::NoAddresses.into())
+    }
+    let template = builder.template().borrow();
+    let mixed = addrs.iter().any(|a| a.is_ipv4()) &&
+                addrs.iter().any(|a| a.is_ipv6());
+    let v6only = mixed && !template.has_opt(libc::IPPROTO_IPV6, ext::IPV6_V6ONLY);
+
+    let mut set = ListenerSet::new();
+    for addr in addrs {
+        let addr = SockAddr::from(addr);
+        let sock = try!(template.create_unbound(addr.family()));
+        if v6only && addr.family() == libc::AF_INET6 {
+            let raw = sock.as_inner().raw() as ext::Socket;
+            try!(ext::setopt(raw, libc::IPPROTO_IPV6, ext::IPV6_V6ONLY, 1 as c_int));
+        }
+        try!(sock.bind(&addr));
+        try!(sock.listen(backlog));
+        set.push(sock.into_inner().into_tcp_listener());
+    }
+    Ok(set)
+}
@@ -0,0 +1,100 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use libc::{self, c_int};
+
+/// How `bind` chooses an address when a name resolves to more than one.
+///
+/// After the policy has narrowed the resolved addresses down, exactly one
+/// must remain; otherwise binding fails with an `Error::MultipleAddresses`
+/// naming the candidates.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddrSelection {
+    /// Use the first address, in the order the name resolved to them.
+    First,
+    /// Use the IPv4 address, or the IPv6 address if there is no IPv4 one.
+    PreferV4,
+    /// Use the IPv6 address, or the IPv4 address if there is no IPv6 one.
+    PreferV6,
+    /// Use the address of the same family as the socket being bound.
+    ///
+    /// This is the policy used by the `bind` methods of the builders, so for
+    /// example an IPv4 builder binds `localhost` to `127.0.0.1` even when it
+    /// also resolves to `::1`. If there is no address of the socket's family,
+    /// or the family is not yet known as with `TcpBuilder::new_deferred`, all
+    /// of the resolved addresses are candidates.
+    MatchFamily,
+}
+
+/// Resolves `addr` and picks the address to bind a socket of `family` to
+/// according to `selection`.
+pub fn select<T>(addr: T, selection: AddrSelection, family: c_int)
+                 -> io::Result<SocketAddr>
+    where T: ToSocketAddrs
+{
+    let addrs = try!(addr.to_socket_addrs()).collect::<Vec<_>>();
+    if addrs.is_empty() {
+        return Err(::Error::NoAddresses.into())
+    }
+    let v4 = match selection {
+        AddrSelection::First => return Ok(addrs[0]),
+        AddrSelection::PreferV4 => true,
+        AddrSelection::PreferV6 => false,
+        AddrSelection::MatchFamily if family == libc::AF_INET => true,
+        AddrSelection::MatchFamily if family == libc::AF_INET6 => false,
+        AddrSelection::MatchFamily => return one(addrs),
+    };
+    let preferred = addrs.iter().cloned().filter(|a| {
+        match *a {
+            SocketAddr::V4(..) => v4,
+            SocketAddr::V6(..) => !v4,
+        }
+    }).collect::<Vec<_>>();
+    one(if preferred.is_empty() {addrs} else {preferred})
+}
+
+fn one(candidates: Vec<SocketAddr>) -> io::Result<SocketAddr> {
+    if candidates.len() == 1 {
+        Ok(candidates[0])
+    } else {
+        Err(::Error::MultipleAddresses(candidates).into())
+    }
+}
+
+/// Calls `bind` with `ip` and each port from `start` to `end` inclusive in
+/// turn until it succeeds, returning the port it succeeded with.
+///
+/// Ports which are in use are skipped, and any other error is returned
+/// immediately. If `random` is set the ports are tried starting from a
+/// random one, wrapping around to the start of the range.
+pub fn bind_port_range<F>(ip: IpAddr, lo: u16, hi: u16, random: bool,
+                          mut bind: F) -> io::Result<u16>
+    where F: FnMut(&SocketAddr) -> io::Result<()>
+{
+    let n = if lo > hi {0} else {hi as u64 - lo as u64 + 1};
+    let offset = if random && n > 0 {
+        RandomState::new().build_hasher().finish() % n
+    } else {
+        0
+    };
+    for i in 0..n {
+        let port = lo + ((offset + i) % n) as u16;
+        match bind(&SocketAddr::new(ip, port)) {
+            Ok(()) => return Ok(port),
+            Err(ref e) if e.kind() == io::ErrorKind::AddrInUse => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Err(::Error::NoFreePort.into())
+}
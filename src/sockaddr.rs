@@ -0,0 +1,499 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![allow(bad_style)]
+
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::ptr;
+use std::slice;
+use std::net::{SocketAddr, SocketAddrV4, SocketAddrV6, Ipv4Addr, Ipv6Addr};
+use libc::{self, c_int, sockaddr, sockaddr_storage, socklen_t, sa_family_t};
+
+use ext;
+
+#[cfg(unix)] use std::ffi::OsStr;
+#[cfg(unix)] use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)] use std::path::{Path, PathBuf};
+#[cfg(unix)] use unix::UnixAddr;
+
+#[cfg(any(target_os = "linux", target_os = "android"))] const AF_NETLINK: c_int = 16;
+#[cfg(any(target_os = "linux", target_os = "android"))] const AF_PACKET: c_int = 17;
+#[cfg(any(target_os = "linux", target_os = "android"))] const AF_VSOCK: c_int = 40;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[repr(C)]
+struct sockaddr_nl {
+    nl_family: sa_family_t,
+    nl_pad: u16,
+    nl_pid: u32,
+    nl_groups: u32,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[repr(C)]
+struct sockaddr_ll {
+    sll_family: sa_family_t,
+    sll_protocol: u16,
+    sll_ifindex: c_int,
+    sll_hatype: u16,
+    sll_pkttype: u8,
+    sll_halen: u8,
+    sll_addr: [u8; 8],
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[repr(C)]
+struct sockaddr_vm {
+    svm_family: sa_family_t,
+    svm_reserved1: u16,
+    svm_port: u32,
+    svm_cid: u32,
+    svm_zero: [u8; 4],
+}
+
+/// The address of a socket, in any address family.
+///
+/// This is backed by a `sockaddr_storage`, so it can hold an address of any
+/// family the platform supports, and is what the `bind_addr` and
+/// `connect_addr` family of methods in this crate operate on. Constructors
+/// and accessors are provided for the IP, Unix domain, and (on Linux) netlink,
+/// packet and vsock families; addresses of other families can be created from
+/// raw parts.
+#[derive(Clone, Copy)]
+pub struct SockAddr {
+    storage: sockaddr_storage,
+    len: socklen_t,
+}
+
+impl SockAddr {
+    /// Constructs a `SockAddr` from a pointer to a `sockaddr` of some family
+    /// and its length.
+    ///
+    /// This is unsafe as `addr` must point to at least `len` readable bytes.
+    /// Lengths larger than a `sockaddr_storage` are truncated.
+    pub unsafe fn from_raw_parts(addr: *const sockaddr, len: socklen_t) -> SockAddr {
+        let mut storage: sockaddr_storage = mem::zeroed();
+        let len = cmp::min(len as usize, mem::size_of_val(&storage));
+        ptr::copy_nonoverlapping(addr as *const u8,
+                                 &mut storage as *mut _ as *mut u8,
+                                 len);
+        SockAddr { storage: storage, len: len as socklen_t }
+    }
+
+    /// Returns the address family of this address, one of the `AF_*`
+    /// constants.
+    pub fn family(&self) -> i32 {
+        self.storage.ss_family as i32
+    }
+
+    /// Returns the length of the address, suitable to pass to the system
+    /// along with `as_ptr`.
+    pub fn len(&self) -> socklen_t {
+        self.len
+    }
+
+    /// Returns a pointer to the underlying `sockaddr`.
+    pub fn as_ptr(&self) -> *const sockaddr {
+        &self.storage as *const _ as *const _
+    }
+
+    /// Returns this address as a `SocketAddrV4` if it is in the `AF_INET`
+    /// family.
+    pub fn as_inet(&self) -> Option<SocketAddrV4> {
+        if self.family() != libc::AF_INET {
+            return None
+        }
+        let a: &libc::sockaddr_in = unsafe { self.cast() };
+        Some(SocketAddrV4::new(in_addr2ip(&a.sin_addr), ::ntoh(a.sin_port)))
+    }
+
+    /// Returns this address as a `SocketAddrV6` if it is in the `AF_INET6`
+    /// family.
+    pub fn as_inet6(&self) -> Option<SocketAddrV6> {
+        if self.family() != libc::AF_INET6 {
+            return None
+        }
+        let a: &libc::sockaddr_in6 = unsafe { self.cast() };
+        Some(SocketAddrV6::new(in6_addr2ip(&a.sin6_addr), ::ntoh(a.sin6_port),
+                               ::ntoh(a.sin6_flowinfo), a.sin6_scope_id))
+    }
+
+    /// Returns this address as a `SocketAddr` if it is in the `AF_INET` or
+    /// `AF_INET6` family.
+    pub fn as_socket_addr(&self) -> Option<SocketAddr> {
+        self.as_inet().map(SocketAddr::V4)
+            .or_else(|| self.as_inet6().map(SocketAddr::V6))
+    }
+
+    unsafe fn cast<T>(&self) -> &T {
+        &*(&self.storage as *const _ as *const T)
+    }
+
+    fn new<T>(addr: &T) -> SockAddr {
+        unsafe {
+            SockAddr::from_raw_parts(addr as *const _ as *const _,
+                                     mem::size_of::<T>() as socklen_t)
+        }
+    }
+}
+
+#[cfg(unix)]
+impl SockAddr {
+    /// Constructs an `AF_UNIX` address for the given filesystem path.
+    ///
+    /// An error is returned if the path is too long or contains a null byte.
+    pub fn unix<P: AsRef<Path>>(path: P) -> io::Result<SockAddr> {
+        SockAddr::from_unix(&UnixAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Constructs an `AF_UNIX` address for the given name in the Linux
+    /// abstract namespace.
+    ///
+    /// The name should not include the leading null byte. An error is
+    /// returned if the name is too long.
+    pub fn unix_abstract<N: AsRef<[u8]>>(name: N) -> io::Result<SockAddr> {
+        SockAddr::from_unix(&UnixAddr::abstract_name(name))
+    }
+
+    /// Constructs an `AF_UNIX` address from a `UnixAddr`.
+    pub fn from_unix(addr: &UnixAddr) -> io::Result<SockAddr> {
+        let mut raw: libc::sockaddr_un = unsafe { mem::zeroed() };
+        raw.sun_family = libc::AF_UNIX as sa_family_t;
+
+        let (bytes, prefix) = match *addr {
+            UnixAddr::Unnamed => {
+                let len = sun_path_offset() as socklen_t;
+                set_sun_len(&mut raw, len);
+                return Ok(unsafe {
+                    SockAddr::from_raw_parts(&raw as *const _ as *const _, len)
+                })
+            }
+            UnixAddr::Pathname(ref path) => {
+                let bytes = path.as_os_str().as_bytes();
+                if bytes.contains(&0) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                              "paths may not contain interior null bytes"))
+                }
+                (bytes, 0)
+            }
+            UnixAddr::Abstract(ref name) => (&name[..], 1),
+        };
+
+        // Pathnames leave room for the trailing null byte, and abstract names
+        // for the leading one.
+        if bytes.len() >= raw.sun_path.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "path must be shorter than SUN_LEN"))
+        }
+        for (dst, src) in raw.sun_path[prefix..].iter_mut().zip(bytes.iter()) {
+            *dst = *src as libc::c_char;
+        }
+
+        let len = (sun_path_offset() + bytes.len() + 1) as socklen_t;
+        set_sun_len(&mut raw, len);
+        Ok(unsafe { SockAddr::from_raw_parts(&raw as *const _ as *const _, len) })
+    }
+
+    /// Returns this address as a `UnixAddr` if it is in the `AF_UNIX` family.
+    pub fn as_unix(&self) -> Option<UnixAddr> {
+        if self.family() != libc::AF_UNIX {
+            return None
+        }
+        let raw: &libc::sockaddr_un = unsafe { self.cast() };
+        let len = (self.len as usize).saturating_sub(sun_path_offset());
+        let path = unsafe {
+            slice::from_raw_parts(raw.sun_path.as_ptr() as *const u8,
+                                  cmp::min(len, raw.sun_path.len()))
+        };
+        Some(if path.is_empty() {
+            UnixAddr::Unnamed
+        } else if path[0] == 0 {
+            UnixAddr::Abstract(path[1..].to_vec())
+        } else {
+            let end = path.iter().position(|b| *b == 0).unwrap_or(path.len());
+            UnixAddr::Pathname(PathBuf::from(OsStr::from_bytes(&path[..end])))
+        })
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl SockAddr {
+    /// Constructs an `AF_NETLINK` address with the given port id and
+    /// multicast groups mask.
+    pub fn netlink(pid: u32, groups: u32) -> SockAddr {
+        SockAddr::new(&sockaddr_nl {
+            nl_family: AF_NETLINK as sa_family_t,
+            nl_pad: 0,
+            nl_pid: pid,
+            nl_groups: groups,
+        })
+    }
+
+    /// Returns the port id and multicast groups mask of this address if it is
+    /// in the `AF_NETLINK` family.
+    pub fn as_netlink(&self) -> Option<(u32, u32)> {
+        if self.family() != AF_NETLINK {
+            return None
+        }
+        let a: &sockaddr_nl = unsafe { self.cast() };
+        Some((a.nl_pid, a.nl_groups))
+    }
+
+    /// Constructs an `AF_PACKET` address for the given protocol, interface
+    /// index and hardware address.
+    ///
+    /// The protocol is one of the `ETH_P_*` values in host byte order. The
+    /// hardware address may be empty when binding, and can be at most 8
+    /// bytes long.
+    pub fn packet(protocol: u16, ifindex: i32, hwaddr: &[u8]) -> io::Result<SockAddr> {
+        let mut addr = sockaddr_ll {
+            sll_family: AF_PACKET as sa_family_t,
+            sll_protocol: ::hton(protocol),
+            sll_ifindex: ifindex as c_int,
+            sll_hatype: 0,
+            sll_pkttype: 0,
+            sll_halen: hwaddr.len() as u8,
+            sll_addr: [0; 8],
+        };
+        if hwaddr.len() > addr.sll_addr.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "hardware address must be at most 8 bytes"))
+        }
+        addr.sll_addr[..hwaddr.len()].copy_from_slice(hwaddr);
+        Ok(SockAddr::new(&addr))
+    }
+
+    /// Returns the protocol, interface index and hardware address of this
+    /// address if it is in the `AF_PACKET` family.
+    pub fn as_packet(&self) -> Option<(u16, i32, Vec<u8>)> {
+        if self.family() != AF_PACKET {
+            return None
+        }
+        let a: &sockaddr_ll = unsafe { self.cast() };
+        let len = cmp::min(a.sll_halen as usize, a.sll_addr.len());
+        Some((::ntoh(a.sll_protocol), a.sll_ifindex as i32,
+              a.sll_addr[..len].to_vec()))
+    }
+
+    /// Constructs an `AF_VSOCK` address with the given context id and port.
+    pub fn vsock(cid: u32, port: u32) -> SockAddr {
+        SockAddr::new(&sockaddr_vm {
+            svm_family: AF_VSOCK as sa_family_t,
+            svm_reserved1: 0,
+            svm_port: port,
+            svm_cid: cid,
+            svm_zero: [0; 4],
+        })
+    }
+
+    /// Returns the context id and port of this address if it is in the
+    /// `AF_VSOCK` family.
+    pub fn as_vsock(&self) -> Option<(u32, u32)> {
+        if self.family() != AF_VSOCK {
+            return None
+        }
+        let a: &sockaddr_vm = unsafe { self.cast() };
+        Some((a.svm_cid, a.svm_port))
+    }
+}
+
+impl From<SocketAddrV4> for SockAddr {
+    fn from(addr: SocketAddrV4) -> SockAddr {
+        let mut raw: libc::sockaddr_in = unsafe { mem::zeroed() };
+        raw.sin_family = libc::AF_INET as sa_family_t;
+        raw.sin_port = ::hton(addr.port());
+        raw.sin_addr = ext::ip2in_addr(addr.ip());
+        SockAddr::new(&raw)
+    }
+}
+
+impl From<SocketAddrV6> for SockAddr {
+    fn from(addr: SocketAddrV6) -> SockAddr {
+        let mut raw: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+        raw.sin6_family = libc::AF_INET6 as sa_family_t;
+        raw.sin6_port = ::hton(addr.port());
+        raw.sin6_flowinfo = ::hton(addr.flowinfo());
+        raw.sin6_addr = ext::ip2in6_addr(addr.ip());
+        raw.sin6_scope_id = addr.scope_id();
+        SockAddr::new(&raw)
+    }
+}
+
+impl From<SocketAddr> for SockAddr {
+    fn from(addr: SocketAddr) -> SockAddr {
+        match addr {
+            SocketAddr::V4(a) => a.into(),
+            SocketAddr::V6(a) => a.into(),
+        }
+    }
+}
+
+/// Converts an IPv4 address into the IPv4-mapped IPv6 address
+/// `::ffff:a.b.c.d`, leaving IPv6 addresses alone.
+pub fn map_v4(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(a) => {
+            SocketAddr::V6(SocketAddrV6::new(a.ip().to_ipv6_mapped(),
+                                             a.port(), 0, 0))
+        }
+        a => a,
+    }
+}
+
+/// Converts an IPv4-mapped IPv6 address back into an IPv4 address, leaving
+/// other addresses alone.
+pub fn unmap_v4(addr: SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V6(a) => {
+            match a.ip().segments() {
+                [0, 0, 0, 0, 0, 0xffff, hi, lo] => {
+                    let ip = Ipv4Addr::new((hi >> 8) as u8, hi as u8,
+                                           (lo >> 8) as u8, lo as u8);
+                    SocketAddr::V4(SocketAddrV4::new(ip, a.port()))
+                }
+                _ => SocketAddr::V6(a),
+            }
+        }
+        a => a,
+    }
+}
+
+impl PartialEq for SockAddr {
+    /// Compares the family and address fields of the two addresses, ignoring
+    /// any padding and the unused tail of the storage.
+    fn eq(&self, other: &SockAddr) -> bool {
+        if self.family() != other.family() {
+            return false
+        }
+        match self.family() {
+            libc::AF_INET => return self.as_inet() == other.as_inet(),
+            libc::AF_INET6 => return self.as_inet6() == other.as_inet6(),
+            _ => {}
+        }
+        #[cfg(unix)]
+        {
+            if self.family() == libc::AF_UNIX {
+                return self.as_unix() == other.as_unix()
+            }
+        }
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            match self.family() {
+                AF_NETLINK => return self.as_netlink() == other.as_netlink(),
+                AF_PACKET => return self.as_packet() == other.as_packet(),
+                AF_VSOCK => return self.as_vsock() == other.as_vsock(),
+                _ => {}
+            }
+        }
+        self.bytes() == other.bytes()
+    }
+}
+
+impl Eq for SockAddr {}
+
+impl SockAddr {
+    fn bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self.as_ptr() as *const u8, self.len as usize)
+        }
+    }
+}
+
+impl fmt::Debug for SockAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(addr) = self.as_socket_addr() {
+            return write!(f, "SockAddr({})", addr)
+        }
+        #[cfg(unix)]
+        {
+            if let Some(addr) = self.as_unix() {
+                return write!(f, "SockAddr({:?})", addr)
+            }
+        }
+        write!(f, "SockAddr {{ family: {}, len: {} }}", self.family(), self.len)
+    }
+}
+
+/// A value which can be converted into a `SockAddr`.
+///
+/// This is implemented for `SockAddr` itself, the standard library's IP socket
+/// address types and, on Unix, `UnixAddr`, and is accepted by the
+/// `bind_addr` and `connect_addr` family of methods in this crate.
+pub trait ToSockAddr {
+    /// Converts this value into a `SockAddr`.
+    fn to_sock_addr(&self) -> io::Result<SockAddr>;
+}
+
+impl ToSockAddr for SockAddr {
+    fn to_sock_addr(&self) -> io::Result<SockAddr> { Ok(*self) }
+}
+
+impl ToSockAddr for SocketAddr {
+    fn to_sock_addr(&self) -> io::Result<SockAddr> { Ok((*self).into()) }
+}
+
+impl ToSockAddr for SocketAddrV4 {
+    fn to_sock_addr(&self) -> io::Result<SockAddr> { Ok((*self).into()) }
+}
+
+impl ToSockAddr for SocketAddrV6 {
+    fn to_sock_addr(&self) -> io::Result<SockAddr> { Ok((*self).into()) }
+}
+
+#[cfg(unix)]
+impl ToSockAddr for UnixAddr {
+    fn to_sock_addr(&self) -> io::Result<SockAddr> { SockAddr::from_unix(self) }
+}
+
+/// Calls `f` with a buffer large enough for any address, such as for
+/// getsockname(2) or accept(2), and returns the address it filled in.
+pub fn with_storage<F>(f: F) -> io::Result<SockAddr>
+    where F: FnOnce(*mut sockaddr, *mut socklen_t) -> io::Result<()>
+{
+    let mut storage: sockaddr_storage = unsafe { mem::zeroed() };
+    let mut len = mem::size_of_val(&storage) as socklen_t;
+    try!(f(&mut storage as *mut _ as *mut _, &mut len));
+    let len = cmp::min(len as usize, mem::size_of_val(&storage)) as socklen_t;
+    Ok(SockAddr { storage: storage, len: len })
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd",
+          target_os = "dragonfly", target_os = "openbsd",
+          target_os = "netbsd"))]
+fn set_sun_len(raw: &mut libc::sockaddr_un, len: socklen_t) {
+    raw.sun_len = len as u8;
+}
+
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios",
+                        target_os = "freebsd", target_os = "dragonfly",
+                        target_os = "openbsd", target_os = "netbsd"))))]
+fn set_sun_len(_raw: &mut libc::sockaddr_un, _len: socklen_t) {}
+
+#[cfg(unix)]
+fn sun_path_offset() -> usize {
+    let addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    let base = &addr as *const _ as usize;
+    let path = &addr.sun_path as *const _ as usize;
+    path - base
+}
+
+fn in_addr2ip(addr: &libc::in_addr) -> Ipv4Addr {
+    let ip = ::ntoh(addr.s_addr);
+    Ipv4Addr::new((ip >> 24) as u8, (ip >> 16) as u8, (ip >> 8) as u8, ip as u8)
+}
+
+fn in6_addr2ip(addr: &libc::in6_addr) -> Ipv6Addr {
+    let s = addr.s6_addr;
+    Ipv6Addr::new(::ntoh(s[0]), ::ntoh(s[1]), ::ntoh(s[2]), ::ntoh(s[3]),
+                  ::ntoh(s[4]), ::ntoh(s[5]), ::ntoh(s[6]), ::ntoh(s[7]))
+}
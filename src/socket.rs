@@ -8,27 +8,42 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::cell::RefCell;
 use std::fmt;
 use std::io;
 use std::mem;
-use std::net::SocketAddr;
-use libc::{self, c_int, socklen_t, sockaddr};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::slice;
+use libc::{self, c_int};
 
+use ext;
+use sockaddr::{self, SockAddr};
 use sys;
 
+#[cfg(unix)] const CONNECT_IN_PROGRESS: c_int = libc::EINPROGRESS;
+#[cfg(windows)] const CONNECT_IN_PROGRESS: c_int = libc::WSAEWOULDBLOCK;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const SO_PROTOCOL: c_int = 38;
+#[cfg(target_os = "freebsd")]
+const SO_PROTOCOL: c_int = 0x1016;
+
+/// The family of a `Template` whose family is not yet known.
+pub const AF_UNSPEC: c_int = 0;
+
 pub struct Socket {
     inner: sys::Socket,
 }
 
 impl Socket {
-    pub fn new(family: c_int, ty: c_int) -> io::Result<Socket> {
-        Ok(Socket { inner: try!(sys::Socket::new(family, ty)) })
+    pub fn new(family: c_int, ty: c_int, protocol: c_int) -> io::Result<Socket> {
+        Ok(Socket { inner: try!(sys::Socket::new(family, ty, protocol)) })
     }
 
-    pub fn bind(&self, addr: &SocketAddr) -> io::Result<()> {
-        let (addr, len) = addr2raw(addr);
+    pub fn bind(&self, addr: &SockAddr) -> io::Result<()> {
         unsafe {
-            ::cvt(libc::bind(self.inner.raw(), addr, len)).map(|_| ())
+            ::cvt(libc::bind(self.inner.raw(), addr.as_ptr(), addr.len()))
+                .map(|_| ())
         }
     }
 
@@ -38,12 +53,344 @@ impl Socket {
         }
     }
 
-    pub fn connect(&self, addr: &SocketAddr) -> io::Result<()> {
-        let (addr, len) = addr2raw(addr);
+    pub fn connect(&self, addr: &SockAddr) -> io::Result<()> {
         unsafe {
-            ::cvt(libc::connect(self.inner.raw(), addr, len)).map(|_| ())
+            ::cvt(libc::connect(self.inner.raw(), addr.as_ptr(), addr.len()))
+                .map(|_| ())
+        }
+    }
+
+    /// Connects to `addr`, failing with `TimedOut` if the connection is not
+    /// established within `ms` milliseconds.
+    ///
+    /// The connect is performed in nonblocking mode, and the socket is
+    /// returned to blocking mode afterwards whether or not it succeeded.
+    pub fn connect_timeout(&self, addr: &SockAddr, ms: u32) -> io::Result<()> {
+        try!(self.set_nonblocking(true));
+        let ret = self.start_connect(addr).and_then(|done| {
+            if done {
+                return Ok(())
+            }
+            if !try!(self.inner.wait_writable(ms)) {
+                return Err(::Error::TimedOut.into())
+            }
+            self.connect_result()
+        });
+        try!(self.set_nonblocking(false));
+        ret
+    }
+
+    /// Starts connecting a nonblocking socket to `addr`, returning whether
+    /// the connection completed immediately.
+    ///
+    /// Otherwise the socket becomes writable once the attempt has finished,
+    /// and `connect_result` will return its outcome.
+    pub fn start_connect(&self, addr: &SockAddr) -> io::Result<bool> {
+        match self.connect(addr) {
+            Ok(()) => Ok(true),
+            Err(ref e) if e.raw_os_error() == Some(CONNECT_IN_PROGRESS) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the outcome of a nonblocking connect which has finished.
+    pub fn connect_result(&self) -> io::Result<()> {
+        let sock = self.inner.raw() as ext::Socket;
+        match try!(ext::getopt(sock, libc::SOL_SOCKET, libc::SO_ERROR)) {
+            0 => Ok(()),
+            n => Err(ext::int2err(n).unwrap()),
         }
     }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        ext::set_nonblocking(self.inner.raw() as ext::Socket, nonblocking)
+    }
+
+    /// Returns the address family of this socket, as reported by
+    /// getsockname(2).
+    pub fn family(&self) -> io::Result<c_int> {
+        let addr = try!(sockaddr::with_storage(|addr, len| unsafe {
+            ::cvt(libc::getsockname(self.inner.raw(), addr, len)).map(|_| ())
+        }));
+        Ok(addr.family())
+    }
+
+    /// Returns the protocol of this socket, or 0 where the platform cannot
+    /// report it.
+    #[cfg(any(target_os = "linux", target_os = "android",
+              target_os = "freebsd"))]
+    pub fn protocol(&self) -> io::Result<c_int> {
+        let sock = self.inner.raw() as ext::Socket;
+        ext::getopt(sock, libc::SOL_SOCKET, SO_PROTOCOL)
+    }
+
+    /// Returns the protocol of this socket, or 0 where the platform cannot
+    /// report it.
+    #[cfg(not(any(target_os = "linux", target_os = "android",
+                  target_os = "freebsd")))]
+    pub fn protocol(&self) -> io::Result<c_int> {
+        Ok(0)
+    }
+
+    fn setopt_raw(&self, level: c_int, name: c_int, payload: &[u8])
+                  -> io::Result<()> {
+        ext::setopt_bytes(self.inner.raw() as ext::Socket, level, name, payload)
+    }
+}
+
+/// How a builder's socket was created and configured, recorded so that the
+/// same configuration can be replayed onto new sockets.
+#[derive(Clone)]
+pub struct Template {
+    family: c_int,
+    ty: c_int,
+    protocol: c_int,
+    opts: Vec<(c_int, c_int, Vec<u8>)>,
+    bind: Option<SockAddr>,
+}
+
+impl Template {
+    pub fn new(family: c_int, ty: c_int, protocol: c_int) -> Template {
+        Template {
+            family: family,
+            ty: ty,
+            protocol: protocol,
+            opts: Vec::new(),
+            bind: None,
+        }
+    }
+
+    /// Creates a template for a socket which was created elsewhere, querying
+    /// its family, type and protocol.
+    pub fn for_socket(sock: &Socket) -> Template {
+        let raw = sock.inner.raw() as ext::Socket;
+        let ty = ext::getopt(raw, libc::SOL_SOCKET, libc::SO_TYPE);
+        Template::new(sock.family().unwrap_or(libc::AF_INET),
+                      ty.unwrap_or(libc::SOCK_STREAM),
+                      sock.protocol().unwrap_or(0))
+    }
+
+    pub fn family(&self) -> c_int { self.family }
+
+    /// Records an option, replacing any earlier value for it.
+    pub fn setopt(&mut self, level: c_int, name: c_int, payload: &[u8]) {
+        self.opts.retain(|&(l, n, _)| (l, n) != (level, name));
+        self.opts.push((level, name, payload.to_vec()));
+    }
+
+    /// Returns whether sockets from this template are IPv6 sockets which have
+    /// had `IPV6_V6ONLY` turned off, and so can also talk to IPv4 peers.
+    pub fn dual_stack(&self) -> bool {
+        self.family == libc::AF_INET6 && self.opts.iter().any(|&(l, n, ref p)| {
+            (l, n) == (libc::IPPROTO_IPV6, ext::IPV6_V6ONLY) &&
+                p.iter().all(|&b| b == 0)
+        })
+    }
+
+    /// Maps an IPv4 address to its IPv4-mapped IPv6 form if sockets from
+    /// this template are dual-stack, so that they can bind or connect to it.
+    pub fn map(&self, addr: &SockAddr) -> SockAddr {
+        match addr.as_inet() {
+            Some(a) if self.dual_stack() => {
+                sockaddr::map_v4(SocketAddr::V4(a)).into()
+            }
+            _ => *addr,
+        }
+    }
+
+    /// Records the address the socket was bound to.
+    pub fn bind(&mut self, addr: &SockAddr) {
+        self.bind = Some(*addr);
+    }
+
+    /// Returns whether a value has been recorded for an option.
+    pub fn has_opt(&self, level: c_int, name: c_int) -> bool {
+        self.opts.iter().any(|&(l, n, _)| (l, n) == (level, name))
+    }
+
+    /// Creates a new socket of `family`, applies the recorded options to it,
+    /// and binds it to the recorded address.
+    ///
+    /// If the address is of another family it is translated with
+    /// `bind_addr`, and if it has no equivalent in `family` an error is
+    /// returned rather than leaving the socket unbound.
+    pub fn create(&self, family: c_int) -> io::Result<Socket> {
+        let addr = try!(self.bind_addr(family));
+        let sock = try!(self.create_unbound(family));
+        if let Some(ref addr) = addr {
+            try!(sock.bind(addr));
+        }
+        Ok(sock)
+    }
+
+    /// Creates a new socket of `family` and applies the recorded options to
+    /// it, ignoring any recorded address.
+    ///
+    /// Only the options which apply to `family` are applied, as decided by
+    /// `applies_to`.
+    pub fn create_unbound(&self, family: c_int) -> io::Result<Socket> {
+        let sock = try!(Socket::new(family, self.ty, self.protocol));
+        for &(level, name, ref payload) in self.opts.iter() {
+            if applies_to(level, name, family) {
+                try!(sock.setopt_raw(level, name, payload));
+            }
+        }
+        Ok(sock)
+    }
+
+    /// Returns the recorded address as an address of `family`.
+    ///
+    /// The unspecified IPv4 and IPv6 addresses stand in for each other, and
+    /// an IPv4-mapped IPv6 address for its IPv4 address. Any other address
+    /// of another family is an `Error::BindFamily`.
+    fn bind_addr(&self, family: c_int) -> io::Result<Option<SockAddr>> {
+        let addr = match self.bind {
+            Some(addr) if addr.family() != family => addr,
+            addr => return Ok(addr),
+        };
+        let addr = match addr.as_socket_addr() {
+            Some(addr) => sockaddr::unmap_v4(addr),
+            None => return Err(::Error::BindFamily.into()),
+        };
+        let addr = match (family, addr) {
+            (libc::AF_INET, SocketAddr::V4(..)) => addr,
+            (libc::AF_INET, SocketAddr::V6(ref a))
+                if a.ip().segments() == [0; 8] => {
+                SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0),
+                                                 a.port()))
+            }
+            (libc::AF_INET6, SocketAddr::V4(ref a))
+                if a.ip().octets() == [0; 4] => {
+                SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0,
+                                                               0, 0, 0, 0),
+                                                 a.port(), 0, 0))
+            }
+            _ => return Err(::Error::BindFamily.into()),
+        };
+        Ok(Some(addr.into()))
+    }
+}
+
+/// Returns whether an option recorded at `level` should be applied to a new
+/// socket of `family`.
+///
+/// `IPPROTO_IPV6` options only apply to IPv6 sockets and `IPPROTO_IP`
+/// options to IPv4 sockets, except for those IP options which Linux also
+/// honours on IPv6 sockets.
+fn applies_to(level: c_int, name: c_int, family: c_int) -> bool {
+    match level {
+        libc::IPPROTO_IP => {
+            family == libc::AF_INET ||
+                (family == libc::AF_INET6 && ip_opt_for_v6(name))
+        }
+        libc::IPPROTO_IPV6 => family == libc::AF_INET6,
+        _ => true,
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn ip_opt_for_v6(name: c_int) -> bool {
+    name == ext::IP_BIND_ADDRESS_NO_PORT || name == ext::IP_FREEBIND ||
+        name == ext::IP_TRANSPARENT
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn ip_opt_for_v6(_name: c_int) -> bool {
+    false
+}
+
+/// Implemented by builders which record their configuration in a
+/// `Template`, so that failed connection attempts can be retried on a fresh
+/// socket and one builder can create many sockets.
+pub trait Templated {
+    fn socket(&self) -> &RefCell<Option<Socket>>;
+    fn template(&self) -> &RefCell<Template>;
+
+    /// Sets an option on the builder's current socket, if it has one, and
+    /// records it for the sockets created later.
+    fn setopt<T: Copy>(&self, level: c_int, name: c_int, payload: T)
+                       -> io::Result<()> {
+        let payload = unsafe {
+            slice::from_raw_parts(&payload as *const T as *const u8,
+                                  mem::size_of::<T>())
+        };
+        self.setopt_bytes(level, name, payload)
+    }
+
+    /// Sets an option whose value is an arbitrary string of bytes, in the
+    /// same way as `setopt`.
+    fn setopt_bytes(&self, level: c_int, name: c_int, payload: &[u8])
+                    -> io::Result<()> {
+        if let Some(ref sock) = *self.socket().borrow() {
+            try!(sock.setopt_raw(level, name, payload));
+        }
+        self.template().borrow_mut().setopt(level, name, payload);
+        Ok(())
+    }
+
+    /// Returns the error for an operation which needs the builder's current
+    /// socket when it has none, either because it has been finished or
+    /// because a deferred builder has not created it yet.
+    fn no_socket(&self) -> io::Error {
+        if self.template().borrow().family() == AF_UNSPEC {
+            ::Error::NotCreated.into()
+        } else {
+            ::Error::BuilderFinished.into()
+        }
+    }
+
+    /// Returns an error if the builder's socket has been finished, but not
+    /// if a deferred builder has yet to create it.
+    fn check_unfinished(&self) -> io::Result<()> {
+        if self.socket().borrow().is_some() ||
+           self.template().borrow().family() == AF_UNSPEC {
+            Ok(())
+        } else {
+            Err(::Error::BuilderFinished.into())
+        }
+    }
+
+    /// Returns the raw handle of the builder's current socket.
+    fn raw_socket(&self) -> io::Result<ext::Socket> {
+        match *self.socket().borrow() {
+            Some(ref sock) => Ok(sock.inner.raw() as ext::Socket),
+            None => Err(self.no_socket()),
+        }
+    }
+
+    /// Binds the builder's current socket and records the address for the
+    /// sockets created later.
+    ///
+    /// IPv4 addresses are mapped to IPv6 if the builder is dual-stack. A
+    /// builder whose family is not yet known creates its socket here, with
+    /// the family of `addr`.
+    fn bind_socket(&self, addr: &SockAddr) -> io::Result<()> {
+        let addr = &self.template().borrow().map(addr);
+        let mut socket = self.socket().borrow_mut();
+        if socket.is_none() && self.template().borrow().family() == AF_UNSPEC {
+            let sock = try!(self.template().borrow().create_unbound(addr.family()));
+            *socket = Some(sock);
+            self.template().borrow_mut().family = addr.family();
+        }
+        match *socket {
+            Some(ref sock) => try!(sock.bind(addr)),
+            None => return Err(::Error::BuilderFinished.into()),
+        }
+        self.template().borrow_mut().bind(addr);
+        Ok(())
+    }
+
+    /// Takes the builder's current socket if it is of `family`, and
+    /// otherwise creates a new one of that family from the template.
+    fn take_socket(&self, family: c_int) -> io::Result<Socket> {
+        let template = self.template().borrow();
+        if template.family() == family {
+            if let Some(sock) = self.socket().borrow_mut().take() {
+                return Ok(sock)
+            }
+        }
+        template.create(family)
+    }
 }
 
 impl fmt::Debug for Socket {
@@ -68,14 +415,3 @@ impl ::IntoInner for Socket {
     type Inner = sys::Socket;
     fn into_inner(self) -> sys::Socket { self.inner }
 }
-
-fn addr2raw(addr: &SocketAddr) -> (*const sockaddr, socklen_t) {
-    match *addr {
-        SocketAddr::V4(ref a) => {
-            (a as *const _ as *const _, mem::size_of_val(a) as socklen_t)
-        }
-        SocketAddr::V6(ref a) => {
-            (a as *const _ as *const _, mem::size_of_val(a) as socklen_t)
-        }
-    }
-}
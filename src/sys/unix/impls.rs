@@ -8,10 +8,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::os::unix::io::{FromRawFd, AsRawFd};
+use std::io;
+use std::os::unix::io::{FromRawFd, AsRawFd, RawFd};
 use libc::c_int;
 
-use {TcpBuilder, UdpBuilder, FromInner, AsInner};
+use {TcpBuilder, UdpBuilder, SocketBuilder, FromInner, AsInner, IntoInner};
 use socket::Socket;
 use sys;
 
@@ -22,10 +23,11 @@ impl FromRawFd for TcpBuilder {
     }
 }
 
+/// Returns -1 if the builder has no socket, because it has been finished or
+/// was created with `TcpBuilder::new_deferred` and not yet bound.
 impl AsRawFd for TcpBuilder {
     fn as_raw_fd(&self) -> c_int {
-        // TODO: this unwrap() is very bad
-        self.as_inner().borrow().as_ref().unwrap().as_inner().raw()
+        self.as_inner().borrow().as_ref().map_or(-1, |s| s.as_inner().raw())
     }
 }
 
@@ -42,3 +44,28 @@ impl AsRawFd for UdpBuilder {
         self.as_inner().borrow().as_ref().unwrap().as_inner().raw()
     }
 }
+
+impl FromRawFd for SocketBuilder {
+    unsafe fn from_raw_fd(fd: c_int) -> SocketBuilder {
+        let sock = sys::Socket::from_inner(fd);
+        SocketBuilder::from_inner(Socket::from_inner(sock))
+    }
+}
+
+impl SocketBuilder {
+    /// Returns the raw file descriptor of this builder's socket, or `None`
+    /// if the socket has been finished by `listen`, `connect` or similar.
+    pub fn as_raw_fd_opt(&self) -> Option<RawFd> {
+        self.as_inner().borrow().as_ref().map(|s| s.as_inner().raw())
+    }
+
+    /// Consumes this builder, returning ownership of its raw file
+    /// descriptor.
+    ///
+    /// An error is returned if the socket has already been finished by
+    /// `listen`, `connect` or similar.
+    pub fn into_socket(self) -> io::Result<RawFd> {
+        self.into_inner().map(|s| s.into_inner().into_fd())
+            .ok_or(::Error::BuilderFinished.into())
+    }
+}
@@ -8,42 +8,160 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+#![allow(bad_style)]
+
 use std::io;
 use std::mem;
 use std::net::{TcpListener, TcpStream, UdpSocket};
 use std::os::unix::io::FromRawFd;
+use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
 use libc::{self, c_int};
 
 cfg_if! {
     if #[cfg(any(target_os = "linux", target_os = "android"))] {
         const FIOCLEX: libc::c_ulong = 0x5451;
+
+        /// Flag which may be or'd into the type passed to socket(2) to create
+        /// the socket with close-on-exec already set.
+        ///
+        /// Sockets created by this crate always have this flag set, so it only
+        /// needs to be used with raw calls made outside of it.
+        pub const SOCK_CLOEXEC: c_int = 0o2000000;
+
+        /// Flag which may be or'd into the type passed to socket(2), for
+        /// example through `SocketBuilder::new`, to create the socket in
+        /// nonblocking mode.
+        #[cfg(any(target_arch = "mips", target_arch = "mips64"))]
+        pub const SOCK_NONBLOCK: c_int = 0o200;
+        /// Flag which may be or'd into the type passed to socket(2), for
+        /// example through `SocketBuilder::new`, to create the socket in
+        /// nonblocking mode.
+        #[cfg(not(any(target_arch = "mips", target_arch = "mips64")))]
+        pub const SOCK_NONBLOCK: c_int = 0o4000;
+
+        extern {
+            fn accept4(fd: c_int, addr: *mut libc::sockaddr,
+                       len: *mut libc::socklen_t, flags: c_int) -> c_int;
+        }
     } else {
         const FIOCLEX: libc::c_ulong = 0x20006601;
     }
 }
 
+cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        type nfds_t = libc::c_ulong;
+    } else {
+        type nfds_t = libc::c_uint;
+    }
+}
+
+#[repr(C)]
+struct pollfd {
+    fd: c_int,
+    events: libc::c_short,
+    revents: libc::c_short,
+}
+
+const POLLIN: libc::c_short = 0x1;
+const POLLOUT: libc::c_short = 0x4;
+
 extern {
     fn ioctl(fd: libc::c_int, req: libc::c_ulong, ...) -> libc::c_int;
+    fn poll(fds: *mut pollfd, nfds: nfds_t, timeout: c_int) -> c_int;
 }
 
 mod impls;
+pub mod msg;
 
 pub struct Socket {
     fd: c_int,
 }
 
 impl Socket {
-    pub fn new(family: c_int, ty: c_int) -> io::Result<Socket> {
-        unsafe {
-            let fd = try!(::cvt(libc::socket(family, ty, 0)));
-            ioctl(fd, FIOCLEX);
-            Ok(Socket { fd: fd })
+    /// Creates a new close-on-exec socket.
+    ///
+    /// On Linux the close-on-exec flag is set atomically by passing
+    /// `SOCK_CLOEXEC` to socket(2), so the descriptor can never leak into a
+    /// child spawned concurrently by another thread. Elsewhere (and on
+    /// kernels older than 2.6.27) it is set immediately afterwards. A
+    /// `SOCK_NONBLOCK` flag in `ty` is honored in the same way.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn new(family: c_int, ty: c_int, protocol: c_int) -> io::Result<Socket> {
+        match ::cvt(unsafe { libc::socket(family, ty | SOCK_CLOEXEC, protocol) }) {
+            Ok(fd) => return Ok(Socket { fd: fd }),
+            Err(ref e) if e.raw_os_error() == Some(libc::EINVAL) => {}
+            Err(e) => return Err(e),
         }
+
+        let flags = SOCK_CLOEXEC | SOCK_NONBLOCK;
+        let fd = try!(::cvt(unsafe { libc::socket(family, ty & !flags, protocol) }));
+        let sock = Socket { fd: fd };
+        try!(sock.set_cloexec());
+        if ty & SOCK_NONBLOCK != 0 {
+            try!(::ext::set_nonblocking(fd, true));
+        }
+        Ok(sock)
+    }
+
+    /// Creates a new close-on-exec socket.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn new(family: c_int, ty: c_int, protocol: c_int) -> io::Result<Socket> {
+        let fd = try!(::cvt(unsafe { libc::socket(family, ty, protocol) }));
+        let sock = Socket { fd: fd };
+        try!(sock.set_cloexec());
+        Ok(sock)
     }
 
     pub fn raw(&self) -> c_int { self.fd }
 
-    fn into_fd(self) -> c_int {
+    /// Accepts a new close-on-exec connection on this socket, optionally in
+    /// nonblocking mode, atomically where the platform supports accept4(2).
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn accept(&self, storage: *mut libc::sockaddr,
+                  len: *mut libc::socklen_t,
+                  nonblocking: bool) -> io::Result<Socket> {
+        let flags = SOCK_CLOEXEC | if nonblocking {SOCK_NONBLOCK} else {0};
+        match ::cvt(unsafe { accept4(self.fd, storage, len, flags) }) {
+            Ok(fd) => return Ok(Socket { fd: fd }),
+            Err(ref e) if e.raw_os_error() == Some(libc::ENOSYS) => {}
+            Err(e) => return Err(e),
+        }
+        self.accept_fallback(storage, len, nonblocking)
+    }
+
+    /// Accepts a new close-on-exec connection on this socket, optionally in
+    /// nonblocking mode.
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    pub fn accept(&self, storage: *mut libc::sockaddr,
+                  len: *mut libc::socklen_t,
+                  nonblocking: bool) -> io::Result<Socket> {
+        self.accept_fallback(storage, len, nonblocking)
+    }
+
+    fn accept_fallback(&self, storage: *mut libc::sockaddr,
+                       len: *mut libc::socklen_t,
+                       nonblocking: bool) -> io::Result<Socket> {
+        let fd = try!(::cvt(unsafe { libc::accept(self.fd, storage, len) }));
+        let sock = Socket { fd: fd };
+        try!(sock.set_cloexec());
+        if nonblocking {
+            try!(::ext::set_nonblocking(fd, true));
+        }
+        Ok(sock)
+    }
+
+    /// Waits at most `ms` milliseconds for this socket to become writable,
+    /// returning whether it did.
+    pub fn wait_writable(&self, ms: u32) -> io::Result<bool> {
+        wait_writable(&[self], Some(ms)).map(|ready| ready[0])
+    }
+
+    fn set_cloexec(&self) -> io::Result<()> {
+        ::cvt(unsafe { ioctl(self.fd, FIOCLEX) }).map(|_| ())
+    }
+
+    pub fn into_fd(self) -> c_int {
         let fd = self.fd;
         mem::forget(self);
         fd
@@ -60,6 +178,104 @@ impl Socket {
     pub fn into_udp_socket(self) -> UdpSocket {
         unsafe { UdpSocket::from_raw_fd(self.into_fd()) }
     }
+
+    pub fn into_unix_datagram(self) -> UnixDatagram {
+        unsafe { UnixDatagram::from_raw_fd(self.into_fd()) }
+    }
+
+    pub fn into_unix_listener(self) -> UnixListener {
+        unsafe { UnixListener::from_raw_fd(self.into_fd()) }
+    }
+
+    pub fn into_unix_stream(self) -> UnixStream {
+        unsafe { UnixStream::from_raw_fd(self.into_fd()) }
+    }
+}
+
+/// Waits at most `ms` milliseconds, or indefinitely if `ms` is `None`, for
+/// any of `socks` to become writable, returning which of them are.
+pub fn wait_writable(socks: &[&Socket], ms: Option<u32>) -> io::Result<Vec<bool>> {
+    let fds = socks.iter().map(|s| s.fd).collect::<Vec<_>>();
+    wait(&fds, POLLOUT, ms)
+}
+
+/// Waits at most `ms` milliseconds, or indefinitely if `ms` is `None`, for
+/// any of the raw sockets `fds` to become readable, returning which of them
+/// are.
+pub fn wait_readable(fds: &[c_int], ms: Option<u32>) -> io::Result<Vec<bool>> {
+    wait(fds, POLLIN, ms)
+}
+
+fn wait(fds: &[c_int], events: libc::c_short, ms: Option<u32>)
+        -> io::Result<Vec<bool>> {
+    let mut fds = fds.iter().map(|&fd| {
+        pollfd { fd: fd, events: events, revents: 0 }
+    }).collect::<Vec<_>>();
+    // poll takes a signed timeout where any negative value means forever, so
+    // long waits are clamped rather than wrapped around
+    let timeout = match ms {
+        Some(ms) if ms > c_int::max_value() as u32 => c_int::max_value(),
+        Some(ms) => ms as c_int,
+        None => -1,
+    };
+    loop {
+        let n = unsafe { poll(fds.as_mut_ptr(), fds.len() as nfds_t, timeout) };
+        match ::cvt(n) {
+            Ok(_) => return Ok(fds.iter().map(|fd| fd.revents != 0).collect()),
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+cfg_if! {
+    if #[cfg(any(target_os = "macos", target_os = "ios"))] {
+        #[repr(C)]
+        struct mach_timebase_info {
+            numer: u32,
+            denom: u32,
+        }
+
+        extern {
+            fn mach_absolute_time() -> u64;
+            fn mach_timebase_info(info: *mut mach_timebase_info) -> libc::c_int;
+        }
+
+        /// Returns the time of a monotonic clock, in milliseconds.
+        pub fn now_ms() -> u64 {
+            let mut info = mach_timebase_info { numer: 0, denom: 0 };
+            unsafe {
+                mach_timebase_info(&mut info);
+                let ns = mach_absolute_time() as f64 * info.numer as f64 /
+                         info.denom as f64;
+                (ns / 1_000_000.0) as u64
+            }
+        }
+    } else {
+        #[repr(C)]
+        struct timespec {
+            tv_sec: libc::time_t,
+            tv_nsec: libc::c_long,
+        }
+
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        const CLOCK_MONOTONIC: c_int = 1;
+        #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
+        const CLOCK_MONOTONIC: c_int = 4;
+        #[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+        const CLOCK_MONOTONIC: c_int = 3;
+
+        extern {
+            fn clock_gettime(clock: c_int, ts: *mut timespec) -> c_int;
+        }
+
+        /// Returns the time of a monotonic clock, in milliseconds.
+        pub fn now_ms() -> u64 {
+            let mut ts = timespec { tv_sec: 0, tv_nsec: 0 };
+            unsafe { clock_gettime(CLOCK_MONOTONIC, &mut ts); }
+            ts.tv_sec as u64 * 1000 + ts.tv_nsec as u64 / 1_000_000
+        }
+    }
 }
 
 impl ::FromInner for Socket {
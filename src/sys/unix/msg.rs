@@ -0,0 +1,292 @@
+// Copyright 2015 The Rust Project Developers. See the COPYRIGHT
+// file at the top-level directory of this distribution and at
+// http://rust-lang.org/COPYRIGHT.
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Bindings to sendmsg(2) and recvmsg(2) along with the ancillary data
+//! ("control message") plumbing, which the libc crate does not yet provide.
+
+#![allow(bad_style)]
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::slice;
+use libc::{self, c_int, c_void, size_t, socklen_t, ssize_t};
+
+#[repr(C)]
+struct iovec {
+    iov_base: *mut c_void,
+    iov_len: size_t,
+}
+
+cfg_if! {
+    if #[cfg(any(target_os = "linux", target_os = "android"))] {
+        #[repr(C)]
+        struct msghdr {
+            msg_name: *mut c_void,
+            msg_namelen: socklen_t,
+            msg_iov: *mut iovec,
+            msg_iovlen: size_t,
+            msg_control: *mut c_void,
+            msg_controllen: size_t,
+            msg_flags: c_int,
+        }
+
+        #[repr(C)]
+        struct cmsghdr {
+            cmsg_len: size_t,
+            cmsg_level: c_int,
+            cmsg_type: c_int,
+        }
+
+        type iovlen_t = size_t;
+        type controllen_t = size_t;
+        type cmsglen_t = size_t;
+
+        fn cmsg_alignment() -> usize { mem::size_of::<size_t>() }
+        const MSG_CMSG_CLOEXEC: c_int = 0x40000000;
+        const MSG_CTRUNC: c_int = 0x8;
+    } else {
+        #[repr(C)]
+        struct msghdr {
+            msg_name: *mut c_void,
+            msg_namelen: socklen_t,
+            msg_iov: *mut iovec,
+            msg_iovlen: c_int,
+            msg_control: *mut c_void,
+            msg_controllen: socklen_t,
+            msg_flags: c_int,
+        }
+
+        #[repr(C)]
+        struct cmsghdr {
+            cmsg_len: socklen_t,
+            cmsg_level: c_int,
+            cmsg_type: c_int,
+        }
+
+        type iovlen_t = c_int;
+        type controllen_t = socklen_t;
+        type cmsglen_t = socklen_t;
+
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        fn cmsg_alignment() -> usize { 4 }
+        #[cfg(not(any(target_os = "macos", target_os = "ios")))]
+        fn cmsg_alignment() -> usize { mem::size_of::<libc::c_long>() }
+        const MSG_CMSG_CLOEXEC: c_int = 0;
+        const MSG_CTRUNC: c_int = 0x20;
+    }
+}
+
+pub const SCM_RIGHTS: c_int = 1;
+
+extern {
+    fn sendmsg(fd: c_int, msg: *const msghdr, flags: c_int) -> ssize_t;
+    fn recvmsg(fd: c_int, msg: *mut msghdr, flags: c_int) -> ssize_t;
+}
+
+fn cmsg_align(len: usize) -> usize {
+    let align = cmsg_alignment();
+    (len + align - 1) & !(align - 1)
+}
+
+/// The number of bytes of control buffer needed to hold one control message
+/// with a payload of `len` bytes.
+pub fn cmsg_space(len: usize) -> usize {
+    cmsg_align(mem::size_of::<cmsghdr>()) + cmsg_align(len)
+}
+
+/// A buffer of control messages, aligned suitably for `cmsghdr`.
+pub struct Control {
+    buf: Vec<u64>,
+    len: usize,
+}
+
+impl Control {
+    /// Creates an empty buffer with room for `space` bytes of control
+    /// messages, as computed by `cmsg_space`.
+    pub fn with_space(space: usize) -> Control {
+        // Rounded up to whole `u64`s, which keeps the buffer aligned
+        let words = space / 8 + (space % 8 != 0) as usize;
+        Control { buf: vec![0; words], len: 0 }
+    }
+
+    /// Appends a control message with the given level, type and payload.
+    ///
+    /// Panics if the buffer was not created with enough space.
+    pub fn push(&mut self, level: c_int, ty: c_int, data: &[u8]) {
+        let space = cmsg_space(data.len());
+        assert!(self.len + space <= self.capacity());
+        let hdr_len = cmsg_align(mem::size_of::<cmsghdr>());
+        let start = self.len;
+        let buf = &mut self.bytes_mut()[start..start + space];
+        unsafe {
+            let hdr = buf.as_mut_ptr() as *mut cmsghdr;
+            (*hdr).cmsg_len = (hdr_len + data.len()) as cmsglen_t;
+            (*hdr).cmsg_level = level;
+            (*hdr).cmsg_type = ty;
+            ptr::copy_nonoverlapping(data.as_ptr(), buf[hdr_len..].as_mut_ptr(),
+                                     data.len());
+        }
+        self.len += space;
+    }
+
+    /// Returns an iterator over the `(level, type, payload)` of each control
+    /// message in this buffer.
+    pub fn iter<'a>(&'a self) -> Cmsgs<'a> {
+        Cmsgs { buf: &self.bytes()[..self.len] }
+    }
+
+    /// Closes every file descriptor received in an `SCM_RIGHTS` message in
+    /// this buffer, and empties it.
+    ///
+    /// This is for messages whose descriptors are not wanted, so that a peer
+    /// cannot leak descriptors into this process by sending them unasked.
+    pub fn close_fds(&mut self) {
+        for fd in self.received_fds() {
+            unsafe { libc::close(fd); }
+        }
+        self.len = 0;
+    }
+
+    fn received_fds(&self) -> Vec<c_int> {
+        self.iter().filter(|&(level, ty, _)| {
+            level == libc::SOL_SOCKET && ty == SCM_RIGHTS
+        }).flat_map(|(_, _, data)| fds(data).into_iter()).collect()
+    }
+
+    fn capacity(&self) -> usize {
+        self.buf.len() * 8
+    }
+
+    fn bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self.buf.as_ptr() as *const u8, self.capacity())
+        }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        let len = self.capacity();
+        unsafe {
+            slice::from_raw_parts_mut(self.buf.as_mut_ptr() as *mut u8, len)
+        }
+    }
+}
+
+/// Iterator over the messages in a `Control` buffer.
+pub struct Cmsgs<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Iterator for Cmsgs<'a> {
+    type Item = (c_int, c_int, &'a [u8]);
+
+    fn next(&mut self) -> Option<(c_int, c_int, &'a [u8])> {
+        let hdr_len = cmsg_align(mem::size_of::<cmsghdr>());
+        if self.buf.len() < hdr_len {
+            return None
+        }
+        // The buffer only ever advances by aligned amounts from the aligned
+        // start of a `Control`, so this cast is properly aligned.
+        let hdr = unsafe { &*(self.buf.as_ptr() as *const cmsghdr) };
+        let len = hdr.cmsg_len as usize;
+        if len < hdr_len || len > self.buf.len() {
+            return None
+        }
+        let data = &self.buf[hdr_len..len];
+        let next = cmsg_align(len);
+        self.buf = if next < self.buf.len() { &self.buf[next..] } else { &[] };
+        Some((hdr.cmsg_level, hdr.cmsg_type, data))
+    }
+}
+
+/// Sends `buf` on the socket along with the control messages in `control`.
+pub fn send(fd: c_int, buf: &[u8], control: &Control, flags: c_int)
+            -> io::Result<usize> {
+    let mut iov = iovec {
+        iov_base: buf.as_ptr() as *mut c_void,
+        iov_len: buf.len() as size_t,
+    };
+    let mut msg: msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1 as iovlen_t;
+    if control.len > 0 {
+        msg.msg_control = control.buf.as_ptr() as *mut c_void;
+        msg.msg_controllen = control.len as controllen_t;
+    }
+    let ret = try!(::cvt(unsafe { sendmsg(fd, &msg, flags) }));
+    Ok(ret as usize)
+}
+
+/// Receives data into `buf` and control messages into `control`, returning
+/// the number of bytes read and the flags of the received message.
+///
+/// File descriptors received through `SCM_RIGHTS` are close-on-exec. If
+/// `control` was too small for the control messages, any descriptors which
+/// were received are closed and `Error::ControlTruncated` is returned.
+pub fn recv(fd: c_int, buf: &mut [u8], control: &mut Control, flags: c_int)
+            -> io::Result<(usize, c_int)> {
+    recv_name(fd, buf, control, flags, ptr::null_mut(), ptr::null_mut())
+}
+
+/// Like `recv`, but also returns the address the data was received from.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub fn recv_from(fd: c_int, buf: &mut [u8], control: &mut Control, flags: c_int)
+                 -> io::Result<(usize, c_int, ::SockAddr)> {
+    let mut ret = (0, 0);
+    let addr = try!(::sockaddr::with_storage(|addr, len| {
+        ret = try!(recv_name(fd, buf, control, flags, addr, len));
+        Ok(())
+    }));
+    Ok((ret.0, ret.1, addr))
+}
+
+fn recv_name(fd: c_int, buf: &mut [u8], control: &mut Control, flags: c_int,
+             name: *mut libc::sockaddr, namelen: *mut socklen_t)
+             -> io::Result<(usize, c_int)> {
+    let mut iov = iovec {
+        iov_base: buf.as_mut_ptr() as *mut c_void,
+        iov_len: buf.len() as size_t,
+    };
+    let mut msg: msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1 as iovlen_t;
+    msg.msg_control = control.buf.as_mut_ptr() as *mut c_void;
+    msg.msg_controllen = control.capacity() as controllen_t;
+    if !name.is_null() {
+        msg.msg_name = name as *mut c_void;
+        msg.msg_namelen = unsafe { *namelen };
+    }
+    let ret = try!(::cvt(unsafe {
+        recvmsg(fd, &mut msg, flags | MSG_CMSG_CLOEXEC)
+    }));
+    control.len = msg.msg_controllen as usize;
+    if !name.is_null() {
+        unsafe { *namelen = msg.msg_namelen; }
+    }
+
+    if msg.msg_flags & MSG_CTRUNC != 0 {
+        control.close_fds();
+        return Err(::Error::ControlTruncated.into())
+    }
+    if MSG_CMSG_CLOEXEC == 0 {
+        for fd in control.received_fds() {
+            unsafe { super::ioctl(fd, super::FIOCLEX); }
+        }
+    }
+    Ok((ret as usize, msg.msg_flags))
+}
+
+/// Decodes the payload of an `SCM_RIGHTS` message.
+pub fn fds(data: &[u8]) -> Vec<c_int> {
+    let size = mem::size_of::<c_int>();
+    data.chunks(size).filter(|c| c.len() == size).map(|c| unsafe {
+        ptr::read_unaligned(c.as_ptr() as *const c_int)
+    }).collect()
+}
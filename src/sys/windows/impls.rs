@@ -8,10 +8,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use std::io;
 use std::os::windows::io::{FromRawSocket, AsRawSocket};
 use libc::SOCKET;
 
-use {TcpBuilder, UdpBuilder, FromInner, AsInner};
+use {TcpBuilder, UdpBuilder, SocketBuilder, FromInner, AsInner, IntoInner};
 use socket::Socket;
 use sys;
 
@@ -22,10 +23,11 @@ impl FromRawSocket for TcpBuilder {
     }
 }
 
+/// Returns `INVALID_SOCKET` if the builder has no socket, because it has been
+/// finished or was created with `TcpBuilder::new_deferred` and not yet bound.
 impl AsRawSocket for TcpBuilder {
     fn as_raw_socket(&self) -> SOCKET {
-        // TODO: this unwrap() is very bad
-        self.as_inner().borrow().as_ref().unwrap().as_inner().raw()
+        self.as_inner().borrow().as_ref().map_or(!0, |s| s.as_inner().raw())
     }
 }
 
@@ -42,3 +44,27 @@ impl AsRawSocket for UdpBuilder {
         self.as_inner().borrow().as_ref().unwrap().as_inner().raw()
     }
 }
+
+impl FromRawSocket for SocketBuilder {
+    unsafe fn from_raw_socket(fd: SOCKET) -> SocketBuilder {
+        let sock = sys::Socket::from_inner(fd);
+        SocketBuilder::from_inner(Socket::from_inner(sock))
+    }
+}
+
+impl SocketBuilder {
+    /// Returns the raw socket of this builder, or `None` if the socket has
+    /// been finished by `listen`, `connect` or similar.
+    pub fn as_raw_socket_opt(&self) -> Option<SOCKET> {
+        self.as_inner().borrow().as_ref().map(|s| s.as_inner().raw())
+    }
+
+    /// Consumes this builder, returning ownership of its raw socket.
+    ///
+    /// An error is returned if the socket has already been finished by
+    /// `listen`, `connect` or similar.
+    pub fn into_socket(self) -> io::Result<SOCKET> {
+        self.into_inner().map(|s| s.into_inner().into_socket())
+            .ok_or(::Error::BuilderFinished.into())
+    }
+}
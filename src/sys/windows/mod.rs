@@ -45,10 +45,10 @@ pub struct Socket {
 }
 
 impl Socket {
-    pub fn new(family: c_int, ty: c_int) -> io::Result<Socket> {
+    pub fn new(family: c_int, ty: c_int, protocol: c_int) -> io::Result<Socket> {
         init();
         let socket = try!(unsafe {
-            match WSASocketW(family, ty, 0, 0 as *mut _, 0,
+            match WSASocketW(family, ty, protocol, 0 as *mut _, 0,
                              WSA_FLAG_OVERLAPPED) {
                 INVALID_SOCKET => Err(io::Error::last_os_error()),
                 n => Ok(Socket { socket: n }),
@@ -60,7 +60,7 @@ impl Socket {
 
     pub fn raw(&self) -> SOCKET { self.socket }
 
-    fn into_socket(self) -> SOCKET {
+    pub fn into_socket(self) -> SOCKET {
         let socket = self.socket;
         mem::forget(self);
         socket
@@ -78,6 +78,26 @@ impl Socket {
         unsafe { UdpSocket::from_raw_socket(self.into_socket()) }
     }
 
+    pub fn accept(&self, storage: *mut ::libc::sockaddr,
+                  len: *mut ::libc::socklen_t,
+                  nonblocking: bool) -> io::Result<Socket> {
+        let socket = try!(unsafe {
+            match accept(self.socket, storage as *mut _, len as *mut _) {
+                INVALID_SOCKET => Err(io::Error::last_os_error()),
+                n => Ok(Socket { socket: n }),
+            }
+        });
+        try!(socket.set_no_inherit());
+        if nonblocking {
+            try!(::ext::set_nonblocking(socket.socket as ::libc::SOCKET, true));
+        }
+        Ok(socket)
+    }
+
+    pub fn wait_writable(&self, ms: u32) -> io::Result<bool> {
+        wait_writable(&[self], Some(ms)).map(|ready| ready[0])
+    }
+
     fn set_no_inherit(&self) -> io::Result<()> {
         ::cvt_win(unsafe {
             SetHandleInformation(self.socket as HANDLE, HANDLE_FLAG_INHERIT, 0)
@@ -85,6 +105,59 @@ impl Socket {
     }
 }
 
+pub fn wait_writable(socks: &[&Socket], ms: Option<u32>) -> io::Result<Vec<bool>> {
+    let socks = socks.iter().map(|s| s.socket).collect::<Vec<_>>();
+    wait(&socks, true, ms)
+}
+
+pub fn wait_readable(socks: &[::libc::SOCKET], ms: Option<u32>)
+                     -> io::Result<Vec<bool>> {
+    let socks = socks.iter().map(|&s| s as SOCKET).collect::<Vec<_>>();
+    wait(&socks, false, ms)
+}
+
+fn wait(socks: &[SOCKET], write: bool, ms: Option<u32>) -> io::Result<Vec<bool>> {
+    let mut fds: fd_set = unsafe { mem::zeroed() };
+    if socks.len() > fds.fd_array.len() {
+        return Err(::Error::TooManySockets.into())
+    }
+    for (i, &s) in socks.iter().enumerate() {
+        fds.fd_array[i] = s;
+    }
+    fds.fd_count = socks.len() as u_int;
+    // Failed connections are reported through the exceptional set
+    let mut errorfds = fds;
+    // A null timeout waits forever
+    let timeout = ms.map(|ms| {
+        timeval {
+            tv_sec: (ms / 1000) as c_long,
+            tv_usec: (ms % 1000 * 1000) as c_long,
+        }
+    });
+    let timeout = timeout.as_ref().map_or(0 as *const timeval,
+                                          |t| t as *const timeval);
+    let (readfds, writefds) = if write {
+        (0 as *mut _, &mut fds as *mut _)
+    } else {
+        (&mut fds as *mut _, 0 as *mut _)
+    };
+    match unsafe {
+        select(1, readfds, writefds, &mut errorfds, timeout)
+    } {
+        SOCKET_ERROR => return Err(io::Error::last_os_error()),
+        _ => {}
+    }
+    let ready = |set: &fd_set, s: SOCKET| {
+        set.fd_array[..set.fd_count as usize].iter().any(|&t| t == s)
+    };
+    Ok(socks.iter().map(|&s| ready(&fds, s) || ready(&errorfds, s)).collect())
+}
+
+/// Returns the time of a monotonic clock, in milliseconds.
+pub fn now_ms() -> u64 {
+    unsafe { GetTickCount64() as u64 }
+}
+
 impl ::FromInner for Socket {
     type Inner = SOCKET;
     fn from_inner(socket: SOCKET) -> Socket {
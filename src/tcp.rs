@@ -10,18 +10,24 @@
 
 use std::cell::RefCell;
 use std::io;
-use std::net::{ToSocketAddrs, TcpListener, TcpStream};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, TcpListener, TcpStream};
 use std::fmt;
 use libc;
 
 use IntoInner;
-use socket::Socket;
+use listener::{self, ListenerSet};
+use select::{self, AddrSelection};
+use socket::{self, Socket, Template, Templated};
+use sockaddr::{SockAddr, ToSockAddr};
+
+#[cfg(feature = "nightly")] use std::time::Duration;
 
 /// An "in progress" TCP socket which has not yet been connected or listened.
 ///
 /// Allows configuration of a socket before one of these operations is executed.
 pub struct TcpBuilder {
     socket: RefCell<Option<Socket>>,
+    template: RefCell<Template>,
 }
 
 impl TcpBuilder {
@@ -31,7 +37,7 @@ impl TcpBuilder {
     /// Note that passing other kinds of flags or arguments can be done through
     /// the `FromRaw{Fd,Socket}` implementation.
     pub fn new_v4() -> io::Result<TcpBuilder> {
-        Socket::new(libc::AF_INET, libc::SOCK_STREAM).map(::FromInner::from_inner)
+        Socket::new(libc::AF_INET, libc::SOCK_STREAM, 0).map(::FromInner::from_inner)
     }
 
     /// Constructs a new TcpBuilder with the `AF_INET6` domain, the `SOCK_STREAM`
@@ -40,20 +46,131 @@ impl TcpBuilder {
     /// Note that passing other kinds of flags or arguments can be done through
     /// the `FromRaw{Fd,Socket}` implementation.
     pub fn new_v6() -> io::Result<TcpBuilder> {
-        Socket::new(libc::AF_INET6, libc::SOCK_STREAM).map(::FromInner::from_inner)
+        Socket::new(libc::AF_INET6, libc::SOCK_STREAM, 0).map(::FromInner::from_inner)
+    }
+
+    /// Constructs a new TcpBuilder with the same domain as `addr`, the
+    /// `SOCK_STREAM` type, and with a protocol argument of 0.
+    ///
+    /// This is `new_v4` for an IPv4 address and `new_v6` for an IPv6 one.
+    pub fn for_addr(addr: &SocketAddr) -> io::Result<TcpBuilder> {
+        match *addr {
+            SocketAddr::V4(..) => TcpBuilder::new_v4(),
+            SocketAddr::V6(..) => TcpBuilder::new_v6(),
+        }
+    }
+
+    /// Constructs a new TcpBuilder whose address family is not chosen until
+    /// it is bound or connected.
+    ///
+    /// No socket is created up front. Options set on the builder are
+    /// recorded, and applied to a socket of the matching family once `bind`
+    /// or `connect` has resolved the address. Because the family is unknown,
+    /// `bind` requires the address to resolve to a single address unless
+    /// another `AddrSelection` is given to `bind_with`, and `listen` requires
+    /// the builder to have been bound first. Until then, methods which
+    /// operate on the socket directly, such as `listen` or `take_error`,
+    /// return an error wrapping `Error::NotCreated`, and `as_raw_fd` returns
+    /// -1.
+    pub fn new_deferred() -> io::Result<TcpBuilder> {
+        Ok(TcpBuilder {
+            socket: RefCell::new(None),
+            template: RefCell::new(Template::new(socket::AF_UNSPEC,
+                                                 libc::SOCK_STREAM, 0)),
+        })
+    }
+
+    /// Constructs a new dual-stack TcpBuilder, with the `AF_INET6` domain and
+    /// the `IPV6_V6ONLY` option turned off, so that it can be used with both
+    /// IPv6 and IPv4 peers.
+    ///
+    /// IPv4 addresses passed to `bind` and `connect` are converted to the
+    /// IPv4-mapped IPv6 addresses `::ffff:a.b.c.d`, as they are for any
+    /// `AF_INET6` builder on which `only_v6(false)` has been called. The
+    /// addresses reported by the finished socket are in the mapped form, and
+    /// `TcpListenerExt::accept_unmapped` converts peers back to IPv4.
+    pub fn new_dual_stack() -> io::Result<TcpBuilder> {
+        let builder = try!(TcpBuilder::new_v6());
+        try!(builder.only_v6(false));
+        Ok(builder)
     }
 
     /// Binds this socket to the specified address.
     ///
     /// This function directly corresponds to the bind(2) function on Windows
-    /// and Unix.
+    /// and Unix. If `addr` resolves to more than one address, the one of the
+    /// same family as this socket is used, as with
+    /// `AddrSelection::MatchFamily`.
     pub fn bind<T>(&self, addr: T) -> io::Result<&TcpBuilder>
         where T: ToSocketAddrs
     {
-        self.with_socket(|sock| {
-            let addr = try!(::one_addr(addr));
-            sock.bind(&addr)
-        }).map(|()| self)
+        self.bind_with(addr, AddrSelection::MatchFamily)
+    }
+
+    /// Binds this socket to the specified address, choosing among the
+    /// addresses it resolves to with `selection`.
+    pub fn bind_with<T>(&self, addr: T, selection: AddrSelection)
+                        -> io::Result<&TcpBuilder>
+        where T: ToSocketAddrs
+    {
+        let family = self.template.borrow().family();
+        let addr = try!(select::select(addr, selection, family));
+        self.bind_socket(&addr.into()).map(|()| self)
+    }
+
+    /// Binds this socket to `ip` for outbound connections, leaving the port
+    /// to be chosen when it connects.
+    ///
+    /// This is for selecting the source address of connections. On Linux
+    /// the `IP_BIND_ADDRESS_NO_PORT` option is set first, so no ephemeral
+    /// port is reserved by the bind and the port is instead picked per
+    /// connection by `connect`, which avoids exhausting the ephemeral ports
+    /// of a busy source address. Elsewhere this is the same as binding to
+    /// port 0.
+    pub fn bind_source_ip(&self, ip: IpAddr) -> io::Result<&TcpBuilder> {
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        try!(self.setopt(libc::IPPROTO_IP, ::ext::IP_BIND_ADDRESS_NO_PORT,
+                         1 as libc::c_int));
+        self.bind_socket(&SocketAddr::new(ip, 0).into()).map(|()| self)
+    }
+
+    /// Binds this socket to the first port from `start` to `end` inclusive
+    /// which is not in use on `ip`, returning the port chosen.
+    ///
+    /// The ports are tried in order on the same socket. A port which is in
+    /// use is skipped, while any other error stops the search and is
+    /// returned. If every port is in use an `Error::NoFreePort` of kind
+    /// `AddrInUse` is returned.
+    pub fn bind_port_range(&self, ip: IpAddr, start: u16, end: u16)
+                           -> io::Result<(&TcpBuilder, u16)> {
+        select::bind_port_range(ip, start, end, false, |addr| {
+            self.bind_socket(&(*addr).into())
+        }).map(|port| (self, port))
+    }
+
+    /// Binds this socket to a port from `start` to `end` inclusive which is
+    /// not in use on `ip`, starting from a random port in the range, and
+    /// returns the port chosen.
+    ///
+    /// This is the same as `bind_port_range`, except that the search starts
+    /// at a random port and wraps around to the start of the range, so that
+    /// sockets bound in quick succession do not all contend for the lowest
+    /// free ports.
+    pub fn bind_port_range_random(&self, ip: IpAddr, start: u16, end: u16)
+                                  -> io::Result<(&TcpBuilder, u16)> {
+        select::bind_port_range(ip, start, end, true, |addr| {
+            self.bind_socket(&(*addr).into())
+        }).map(|port| (self, port))
+    }
+
+    /// Binds this socket to the specified `SockAddr`.
+    ///
+    /// This is the same as `bind`, but takes an already-built socket address
+    /// rather than resolving one through `ToSocketAddrs`.
+    pub fn bind_addr<A: ToSockAddr + ?Sized>(&self, addr: &A)
+                                             -> io::Result<&TcpBuilder> {
+        let addr = try!(addr.to_sock_addr());
+        self.bind_socket(&addr).map(|()| self)
     }
 
     /// Mark a socket as ready to accept incoming connection requests using
@@ -72,27 +189,112 @@ impl TcpBuilder {
         })
     }
 
+    /// Creates a listener for every address `addr` resolves to, returning
+    /// them as a `ListenerSet`.
+    ///
+    /// Each listener is a new socket of the address's family which has had
+    /// the options set on this builder applied to it, so this builder's own
+    /// socket is left untouched and should not have been bound. A list of
+    /// addresses can be passed as a slice of `SocketAddr`. If the addresses
+    /// include both IPv4 and IPv6 ones the IPv6 listeners are made
+    /// `IPV6_V6ONLY` unless `only_v6` was set on this builder, so that
+    /// `0.0.0.0` and `::` can be listened on together.
+    pub fn listen_all<T>(&self, addr: T, backlog: i32) -> io::Result<ListenerSet>
+        where T: ToSocketAddrs
+    {
+        listener::listen_all(self, addr, backlog)
+    }
+
     /// Initiate a connection on this socket to the specified address.
     ///
     /// This function directly corresponds to the connect(2) function on Windows
     /// and Unix.
     ///
+    /// If `addr` resolves to several addresses they are tried in turn, and
+    /// every attempt after the first is made on a new socket of the matching
+    /// family which has had the options set on this builder applied to it,
+    /// and is bound to the same address if this builder was bound. An
+    /// attempt fails with `Error::BindFamily` if that address has no
+    /// equivalent in the attempt's family.
+    ///
     /// An error will be returned if `listen` or `connect` has already been
-    /// called on this builder.
+    /// called on this builder. To make further connections with the same
+    /// configuration use `connect_new`.
     pub fn connect<T>(&self, addr: T) -> io::Result<TcpStream>
         where T: ToSocketAddrs
     {
-        self.with_socket(|sock| {
-            let err = io::Error::new(io::ErrorKind::Other,
-                                     "no socket addresses resolved");
-            try!(addr.to_socket_addrs()).fold(Err(err), |prev, addr| {
-                prev.or_else(|_| sock.connect(&addr))
-            })
-        }).and_then(|()| {
-            self.to_tcp_stream()
+        let addrs = try!(addr.to_socket_addrs());
+        self.connect_each(addrs, false, |sock, addr| sock.connect(addr))
+    }
+
+    /// Initiate a connection to the specified address on a new socket which
+    /// has had the options set on this builder applied to it, and is bound
+    /// to the same address if this builder was bound.
+    ///
+    /// This builder's own socket is left untouched, and this may be called
+    /// whether or not it has been finished, so that one configured builder
+    /// can act as a template for many connections. Multiple addresses are
+    /// tried in turn as with `connect`.
+    pub fn connect_new<T>(&self, addr: T) -> io::Result<TcpStream>
+        where T: ToSocketAddrs
+    {
+        let addrs = try!(addr.to_socket_addrs());
+        self.connect_each(addrs, true, |sock, addr| sock.connect(addr))
+    }
+
+    /// Initiate a connection on this socket to the specified `SockAddr`.
+    ///
+    /// This is the same as `connect`, but takes an already-built socket
+    /// address rather than resolving one through `ToSocketAddrs`.
+    pub fn connect_addr<A: ToSockAddr + ?Sized>(&self, addr: &A)
+                                                -> io::Result<TcpStream> {
+        try!(self.check_unfinished());
+        let addr = self.template.borrow().map(&try!(addr.to_sock_addr()));
+        let sock = try!(self.take_socket(addr.family()));
+        try!(sock.connect(&addr));
+        Ok(sock.into_inner().into_tcp_stream())
+    }
+
+    /// Initiate a connection on this socket to the specified address, giving
+    /// up after `ms` milliseconds.
+    ///
+    /// The connection is made in nonblocking mode and then waited on, so
+    /// unlike `connect` this will not block for the operating system's full
+    /// SYN retry schedule. If the timeout elapses an error of kind `TimedOut`
+    /// is returned, and otherwise a failed connection reports the socket's
+    /// pending `SO_ERROR`. The returned stream is in blocking mode.
+    ///
+    /// If `addr` resolves to multiple addresses each is tried in turn, each
+    /// with the full timeout.
+    ///
+    /// Like the crate's other timeouts this takes milliseconds, and the
+    /// `Duration` form, `connect_timeout`, is only available with the
+    /// `nightly` feature.
+    pub fn connect_timeout_ms<T>(&self, addr: T, ms: u32) -> io::Result<TcpStream>
+        where T: ToSocketAddrs
+    {
+        let addrs = try!(addr.to_socket_addrs());
+        self.connect_each(addrs, false, |sock, addr| {
+            sock.connect_timeout(addr, ms)
         })
     }
 
+    /// Initiate a connection on this socket to the specified address, giving
+    /// up after `timeout` has elapsed.
+    ///
+    /// This requires the `nightly` feature, as do all of the crate's
+    /// `Duration` based methods. For more information, see
+    /// [`connect_timeout_ms`][link].
+    ///
+    /// [link]: #method.connect_timeout_ms
+    #[cfg(feature = "nightly")]
+    pub fn connect_timeout<T>(&self, addr: T, timeout: Duration)
+                              -> io::Result<TcpStream>
+        where T: ToSocketAddrs
+    {
+        self.connect_timeout_ms(addr, ::ext::dur2ms(timeout))
+    }
+
     /// Converts this builder into a `TcpStream`
     ///
     /// This function will consume the internal socket and return it re-wrapped
@@ -101,8 +303,7 @@ impl TcpBuilder {
     /// etc.
     pub fn to_tcp_stream(&self) -> io::Result<TcpStream> {
         self.socket.borrow_mut().take().map(|s| s.into_inner().into_tcp_stream())
-            .ok_or(io::Error::new(io::ErrorKind::Other,
-                                  "socket has already been consumed"))
+            .ok_or(::Error::SocketConsumed.into())
     }
 
     /// Converts this builder into a `TcpListener`
@@ -114,8 +315,32 @@ impl TcpBuilder {
     pub fn to_tcp_listener(&self) -> io::Result<TcpListener> {
         self.socket.borrow_mut().take()
             .map(|s| s.into_inner().into_tcp_listener())
-            .ok_or(io::Error::new(io::ErrorKind::Other,
-                                  "socket has already been consumed"))
+            .ok_or(::Error::SocketConsumed.into())
+    }
+
+    /// Connects with `connect` to each of `addrs` in turn, on this builder's
+    /// socket and then on new ones, or only on new ones if `fresh` is set.
+    fn connect_each<I, F>(&self, addrs: I, fresh: bool, mut connect: F)
+                          -> io::Result<TcpStream>
+        where I: Iterator<Item=SocketAddr>,
+              F: FnMut(&Socket, &SockAddr) -> io::Result<()>
+    {
+        if !fresh {
+            try!(self.check_unfinished());
+        }
+        let mut stream = None;
+        try!(::error::connect_each(addrs, |addr| {
+            let addr = self.template.borrow().map(&SockAddr::from(*addr));
+            let sock = if fresh {
+                try!(self.template.borrow().create(addr.family()))
+            } else {
+                try!(self.take_socket(addr.family()))
+            };
+            try!(connect(&sock, &addr));
+            stream = Some(sock.into_inner().into_tcp_stream());
+            Ok(())
+        }));
+        Ok(stream.unwrap())
     }
 
     fn with_socket<F>(&self, f: F) -> io::Result<()>
@@ -123,16 +348,17 @@ impl TcpBuilder {
     {
         match *self.socket.borrow() {
             Some(ref s) => f(s),
-            None => Err(io::Error::new(io::ErrorKind::Other,
-                                       "builder has already finished its socket")),
+            None => Err(self.no_socket()),
         }
     }
 }
 
 impl fmt::Debug for TcpBuilder {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "TcpBuilder {{ socket: {:?} }}",
-               self.socket.borrow().as_ref().unwrap())
+        match *self.socket.borrow() {
+            Some(ref s) => write!(f, "TcpBuilder {{ socket: {:?} }}", s),
+            None => write!(f, "TcpBuilder {{ socket: None }}"),
+        }
     }
 }
 
@@ -144,6 +370,15 @@ impl ::AsInner for TcpBuilder {
 impl ::FromInner for TcpBuilder {
     type Inner = Socket;
     fn from_inner(sock: Socket) -> TcpBuilder {
-        TcpBuilder { socket: RefCell::new(Some(sock)) }
+        let template = Template::for_socket(&sock);
+        TcpBuilder {
+            socket: RefCell::new(Some(sock)),
+            template: RefCell::new(template),
+        }
     }
 }
+
+impl Templated for TcpBuilder {
+    fn socket(&self) -> &RefCell<Option<Socket>> { &self.socket }
+    fn template(&self) -> &RefCell<Template> { &self.template }
+}
@@ -11,17 +11,20 @@
 use std::cell::RefCell;
 use std::fmt;
 use std::io;
-use std::net::{ToSocketAddrs, UdpSocket};
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket};
 use libc;
 
 use IntoInner;
-use socket::Socket;
+use select::{self, AddrSelection};
+use socket::{Socket, Template, Templated};
+use sockaddr::ToSockAddr;
 
 /// An "in progress" UDP socket which has not yet been connected.
 ///
 /// Allows configuration of a socket before the socket is connected.
 pub struct UdpBuilder {
     socket: RefCell<Option<Socket>>,
+    template: RefCell<Template>,
 }
 
 impl UdpBuilder {
@@ -31,7 +34,7 @@ impl UdpBuilder {
     /// Note that passing other kinds of flags or arguments can be done through
     /// the `FromRaw{Fd,Socket}` implementation.
     pub fn new_v4() -> io::Result<UdpBuilder> {
-        Socket::new(libc::AF_INET, libc::SOCK_DGRAM).map(::FromInner::from_inner)
+        Socket::new(libc::AF_INET, libc::SOCK_DGRAM, 0).map(::FromInner::from_inner)
     }
 
     /// Constructs a new UdpBuilder with the `AF_INET6` domain, the `SOCK_DGRAM`
@@ -40,38 +43,114 @@ impl UdpBuilder {
     /// Note that passing other kinds of flags or arguments can be done through
     /// the `FromRaw{Fd,Socket}` implementation.
     pub fn new_v6() -> io::Result<UdpBuilder> {
-        Socket::new(libc::AF_INET6, libc::SOCK_DGRAM).map(::FromInner::from_inner)
+        Socket::new(libc::AF_INET6, libc::SOCK_DGRAM, 0).map(::FromInner::from_inner)
+    }
+
+    /// Constructs a new UdpBuilder with the same domain as `addr`, the
+    /// `SOCK_DGRAM` type, and with a protocol argument of 0.
+    ///
+    /// This is `new_v4` for an IPv4 address and `new_v6` for an IPv6 one.
+    pub fn for_addr(addr: &SocketAddr) -> io::Result<UdpBuilder> {
+        match *addr {
+            SocketAddr::V4(..) => UdpBuilder::new_v4(),
+            SocketAddr::V6(..) => UdpBuilder::new_v6(),
+        }
+    }
+
+    /// Constructs a new dual-stack UdpBuilder, with the `AF_INET6` domain and
+    /// the `IPV6_V6ONLY` option turned off, so that it can be used with both
+    /// IPv6 and IPv4 peers.
+    ///
+    /// IPv4 addresses passed to `bind` are converted to the IPv4-mapped IPv6
+    /// addresses `::ffff:a.b.c.d`, as they are for any `AF_INET6` builder on
+    /// which `only_v6(false)` has been called. Use
+    /// `UdpSocketExt::send_to_mapped` and `recv_from_unmapped` on the
+    /// finished socket to do the same when exchanging datagrams.
+    pub fn new_dual_stack() -> io::Result<UdpBuilder> {
+        let builder = try!(UdpBuilder::new_v6());
+        try!(builder.only_v6(false));
+        Ok(builder)
     }
 
     /// Binds this socket to the specified address.
     ///
     /// This function directly corresponds to the bind(2) function on Windows
-    /// and Unix.
+    /// and Unix. If `addr` resolves to more than one address, the one of the
+    /// same family as this socket is used, as with
+    /// `AddrSelection::MatchFamily`.
     pub fn bind<T>(&self, addr: T) -> io::Result<UdpSocket>
         where T: ToSocketAddrs
     {
-        try!(self.with_socket(|sock| {
-            let addr = try!(::one_addr(addr));
-            sock.bind(&addr)
-        }));
-        Ok(self.socket.borrow_mut().take().unwrap().into_inner().into_udp_socket())
+        self.bind_with(addr, AddrSelection::MatchFamily)
     }
 
-    fn with_socket<F>(&self, f: F) -> io::Result<()>
-        where F: FnOnce(&Socket) -> io::Result<()>
+    /// Binds this socket to the specified address, choosing among the
+    /// addresses it resolves to with `selection`.
+    pub fn bind_with<T>(&self, addr: T, selection: AddrSelection)
+                        -> io::Result<UdpSocket>
+        where T: ToSocketAddrs
     {
-        match *self.socket.borrow() {
-            Some(ref s) => f(s),
-            None => Err(io::Error::new(io::ErrorKind::Other,
-                                       "builder has already finished its socket")),
-        }
+        let family = self.template.borrow().family();
+        let addr = try!(select::select(addr, selection, family));
+        try!(self.bind_socket(&addr.into()));
+        self.to_udp_socket()
+    }
+
+    /// Binds this socket to the first port from `start` to `end` inclusive
+    /// which is not in use on `ip`, returning the socket and the port chosen.
+    ///
+    /// The ports are tried in order on the same socket. A port which is in
+    /// use is skipped, while any other error stops the search and is
+    /// returned. If every port is in use an `Error::NoFreePort` of kind
+    /// `AddrInUse` is returned.
+    pub fn bind_port_range(&self, ip: IpAddr, start: u16, end: u16)
+                           -> io::Result<(UdpSocket, u16)> {
+        self.bind_range(ip, start, end, false)
+    }
+
+    /// Binds this socket to a port from `start` to `end` inclusive which is
+    /// not in use on `ip`, starting from a random port in the range, and
+    /// returns the socket and the port chosen.
+    ///
+    /// This is the same as `bind_port_range`, except that the search starts
+    /// at a random port and wraps around to the start of the range, so that
+    /// sockets bound in quick succession do not all contend for the lowest
+    /// free ports.
+    pub fn bind_port_range_random(&self, ip: IpAddr, start: u16, end: u16)
+                                  -> io::Result<(UdpSocket, u16)> {
+        self.bind_range(ip, start, end, true)
+    }
+
+    /// Binds this socket to the specified `SockAddr`.
+    ///
+    /// This is the same as `bind`, but takes an already-built socket address
+    /// rather than resolving one through `ToSocketAddrs`.
+    pub fn bind_addr<A: ToSockAddr + ?Sized>(&self, addr: &A)
+                                             -> io::Result<UdpSocket> {
+        try!(self.bind_socket(&try!(addr.to_sock_addr())));
+        self.to_udp_socket()
+    }
+
+    fn bind_range(&self, ip: IpAddr, start: u16, end: u16, random: bool)
+                  -> io::Result<(UdpSocket, u16)> {
+        let port = try!(select::bind_port_range(ip, start, end, random, |addr| {
+            self.bind_socket(&(*addr).into())
+        }));
+        self.to_udp_socket().map(|sock| (sock, port))
+    }
+
+    fn to_udp_socket(&self) -> io::Result<UdpSocket> {
+        let sock = try!(self.socket.borrow_mut().take().ok_or(self.no_socket()));
+        Ok(sock.into_inner().into_udp_socket())
     }
 }
 
 impl fmt::Debug for UdpBuilder {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "UdpBuilder {{ socket: {:?} }}",
-               self.socket.borrow().as_ref().unwrap())
+        match *self.socket.borrow() {
+            Some(ref s) => write!(f, "UdpBuilder {{ socket: {:?} }}", s),
+            None => write!(f, "UdpBuilder {{ socket: None }}"),
+        }
     }
 }
 
@@ -83,7 +162,16 @@ impl ::AsInner for UdpBuilder {
 impl ::FromInner for UdpBuilder {
     type Inner = Socket;
     fn from_inner(sock: Socket) -> UdpBuilder {
-        UdpBuilder { socket: RefCell::new(Some(sock)) }
+        let template = Template::for_socket(&sock);
+        UdpBuilder {
+            socket: RefCell::new(Some(sock)),
+            template: RefCell::new(template),
+        }
     }
 }
 
+impl Templated for UdpBuilder {
+    fn socket(&self) -> &RefCell<Option<Socket>> { &self.socket }
+    fn template(&self) -> &RefCell<Template> { &self.template }
+}
+
@@ -1,10 +1,63 @@
 //! Unix-specific extensions to the `std::net` types.
+//!
+//! This module also contains builders for Unix domain sockets, which allow
+//! configuring a socket before it is bound, connected or listened in the same
+//! manner as `TcpBuilder` does for TCP sockets.
 
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
 use std::io;
-use libc::{self, c_int};
+use std::mem;
+use std::net::Shutdown;
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::ops::Deref;
+use std::ptr;
+use std::slice;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+use std::os::unix::io::{FromRawFd, AsRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::{UnixDatagram, UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use libc::{self, c_int, socklen_t};
 
-use {TcpBuilder, UdpBuilder};
+use {TcpBuilder, UdpBuilder, SocketBuilder, AsInner, FromInner, IntoInner};
 use ext::{self, AsSock};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use ext::{IP_FREEBIND, IP_TRANSPARENT};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use select::{self, AddrSelection};
+use socket::{Socket, Templated};
+#[cfg(any(target_os = "linux", target_os = "android"))]
+use socket::Template;
+use sockaddr::{self, SockAddr, ToSockAddr};
+use sys;
+use sys::msg;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub use sys::{SOCK_CLOEXEC, SOCK_NONBLOCK};
+
+#[cfg(any(target_os = "linux", target_os = "android"))] const SO_PASSCRED: c_int = 16;
+#[cfg(any(target_os = "linux", target_os = "android"))] const SO_PEERCRED: c_int = 17;
+#[cfg(any(target_os = "linux", target_os = "android"))] const SCM_CREDENTIALS: c_int = 2;
+#[cfg(any(target_os = "linux", target_os = "android"))] const MSG_NOSIGNAL: c_int = 0x4000;
+#[cfg(not(any(target_os = "linux", target_os = "android")))] const MSG_NOSIGNAL: c_int = 0;
+#[cfg(any(target_os = "linux", target_os = "android"))] const SO_BINDTODEVICE: c_int = 25;
+#[cfg(any(target_os = "linux", target_os = "android"))] const SO_BINDTOIFINDEX: c_int = 62;
+#[cfg(any(target_os = "linux", target_os = "android"))] const IFNAMSIZ: usize = 16;
+#[cfg(any(target_os = "linux", target_os = "android"))] const IPV6_FREEBIND: c_int = 78;
+#[cfg(any(target_os = "linux", target_os = "android"))] const IPV6_TRANSPARENT: c_int = 75;
+#[cfg(any(target_os = "linux", target_os = "android"))] const IP_RECVORIGDSTADDR: c_int = 20;
+#[cfg(any(target_os = "linux", target_os = "android"))] const IPV6_RECVORIGDSTADDR: c_int = 74;
+#[cfg(any(target_os = "linux", target_os = "android"))] const SO_ORIGINAL_DST: c_int = 80;
+const SOCK_SEQPACKET: c_int = 5;
+
+extern {
+    fn umask(mask: libc::mode_t) -> libc::mode_t;
+    fn lchown(path: *const libc::c_char, uid: libc::uid_t, gid: libc::gid_t) -> c_int;
+}
 
 /// Unix-specific extensions for the `TcpBuilder` type in this library.
 pub trait UnixTcpBuilderExt {
@@ -14,12 +67,117 @@ pub trait UnixTcpBuilderExt {
     /// addresses. For IPv4 sockets this means that a socket may bind even when
     /// there's a socket already listening on this port.
     fn reuse_port(&self, reuse: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IP_BIND_ADDRESS_NO_PORT` option on this socket.
+    ///
+    /// When enabled, binding to a port of 0 only records the local address,
+    /// and the port is chosen when the socket connects, so that it only
+    /// needs to be unique for the connection's 4-tuple rather than reserved
+    /// outright. This lets many more outbound connections share a source
+    /// address. It requires Linux 4.2 or later.
+    ///
+    /// See also `TcpBuilder::bind_source_ip`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_address_no_port(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `SO_BINDTODEVICE` option on this socket.
+    ///
+    /// This restricts the socket to sending and receiving through the
+    /// network interface named `interface`, such as `b"eth0"` or a VRF
+    /// device, regardless of the routing table. `None` removes the
+    /// restriction. Before Linux 5.7 this requires the `CAP_NET_RAW`
+    /// capability, and without it an error of kind `PermissionDenied` naming
+    /// the option is returned.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device(&self, interface: Option<&[u8]>) -> io::Result<&Self>;
+
+    /// Set value for the `SO_BINDTOIFINDEX` option on this socket.
+    ///
+    /// This is the same as `bind_device`, but identifies the interface by
+    /// its index rather than its name. It requires Linux 5.0 or later.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device_index(&self, index: Option<u32>) -> io::Result<&Self>;
+
+    /// Set value for the `IP_FREEBIND` option on this socket.
+    ///
+    /// When enabled, the socket may be bound to an address which is not (or
+    /// not yet) assigned to any local interface, so that a service can start
+    /// before its floating address has been configured. On Linux this also
+    /// applies to IPv6 sockets.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IPV6_FREEBIND` option on this socket.
+    ///
+    /// This is the IPv6 equivalent of `freebind`, and requires Linux 4.15 or
+    /// later.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind_v6(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IP_TRANSPARENT` option on this socket.
+    ///
+    /// This allows the socket to bind to and accept connections for
+    /// addresses which are not local, as used by transparent proxies with
+    /// the `TPROXY` iptables target. It requires the `CAP_NET_ADMIN`
+    /// capability, and without it an error of kind `PermissionDenied` naming
+    /// the option is returned. On Linux this also applies to IPv6 sockets.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IPV6_TRANSPARENT` option on this socket.
+    ///
+    /// This is the IPv6 equivalent of `transparent`.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent_v6(&self, enable: bool) -> io::Result<&Self>;
 }
 
 impl UnixTcpBuilderExt for TcpBuilder {
     fn reuse_port(&self, reuse: bool) -> io::Result<&Self> {
-        ext::setopt(self.as_sock(), libc::SOL_SOCKET, libc::SO_REUSEPORT,
-                    reuse as c_int).map(|()| self)
+        self.setopt(libc::SOL_SOCKET, libc::SO_REUSEPORT, reuse as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_address_no_port(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IP, ext::IP_BIND_ADDRESS_NO_PORT, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device(&self, interface: Option<&[u8]>) -> io::Result<&Self> {
+        let interface = interface.unwrap_or(&[]);
+        self.setopt_bytes(libc::SOL_SOCKET, SO_BINDTODEVICE, interface)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device_index(&self, index: Option<u32>) -> io::Result<&Self> {
+        self.setopt(libc::SOL_SOCKET, SO_BINDTOIFINDEX, index.unwrap_or(0) as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IP, IP_FREEBIND, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind_v6(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IPV6, IPV6_FREEBIND, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IP, IP_TRANSPARENT, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent_v6(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IPV6, IPV6_TRANSPARENT, enable as c_int)
+            .map(|()| self)
     }
 }
 
@@ -31,11 +189,1826 @@ pub trait UnixUdpBuilderExt {
     /// addresses. For IPv4 sockets this means that a socket may bind even when
     /// there's a socket already listening on this port.
     fn reuse_port(&self, reuse: bool) -> io::Result<&Self>;
+
+    /// Set value for the `SO_BINDTODEVICE` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::bind_device`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.bind_device
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device(&self, interface: Option<&[u8]>) -> io::Result<&Self>;
+
+    /// Set value for the `SO_BINDTOIFINDEX` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::bind_device_index`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.bind_device_index
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device_index(&self, index: Option<u32>) -> io::Result<&Self>;
+
+    /// Set value for the `IP_FREEBIND` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::freebind`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.freebind
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IPV6_FREEBIND` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::freebind_v6`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.freebind_v6
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind_v6(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IP_TRANSPARENT` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::transparent`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.transparent
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IPV6_TRANSPARENT` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::transparent_v6`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.transparent_v6
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent_v6(&self, enable: bool) -> io::Result<&Self>;
 }
 
 impl UnixUdpBuilderExt for UdpBuilder {
     fn reuse_port(&self, reuse: bool) -> io::Result<&Self> {
-        ext::setopt(self.as_sock(), libc::SOL_SOCKET, libc::SO_REUSEPORT,
-                    reuse as c_int).map(|()| self)
+        self.setopt(libc::SOL_SOCKET, libc::SO_REUSEPORT, reuse as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device(&self, interface: Option<&[u8]>) -> io::Result<&Self> {
+        let interface = interface.unwrap_or(&[]);
+        self.setopt_bytes(libc::SOL_SOCKET, SO_BINDTODEVICE, interface)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device_index(&self, index: Option<u32>) -> io::Result<&Self> {
+        self.setopt(libc::SOL_SOCKET, SO_BINDTOIFINDEX, index.unwrap_or(0) as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IP, IP_FREEBIND, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind_v6(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IPV6, IPV6_FREEBIND, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IP, IP_TRANSPARENT, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent_v6(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IPV6, IPV6_TRANSPARENT, enable as c_int)
+            .map(|()| self)
+    }
+}
+
+/// Unix-specific extensions for the `SocketBuilder` type in this library.
+pub trait UnixSocketBuilderExt {
+    /// Set value for the `SO_REUSEPORT` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::reuse_port`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.reuse_port
+    fn reuse_port(&self, reuse: bool) -> io::Result<&Self>;
+
+    /// Set value for the `SO_BINDTODEVICE` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::bind_device`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.bind_device
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device(&self, interface: Option<&[u8]>) -> io::Result<&Self>;
+
+    /// Set value for the `SO_BINDTOIFINDEX` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::bind_device_index`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.bind_device_index
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device_index(&self, index: Option<u32>) -> io::Result<&Self>;
+
+    /// Set value for the `IP_FREEBIND` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::freebind`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.freebind
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IPV6_FREEBIND` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::freebind_v6`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.freebind_v6
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind_v6(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IP_TRANSPARENT` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::transparent`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.transparent
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent(&self, enable: bool) -> io::Result<&Self>;
+
+    /// Set value for the `IPV6_TRANSPARENT` option on this socket.
+    ///
+    /// This is the same as [`UnixTcpBuilderExt::transparent_v6`][other].
+    ///
+    /// [other]: trait.UnixTcpBuilderExt.html#tymethod.transparent_v6
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent_v6(&self, enable: bool) -> io::Result<&Self>;
+}
+
+impl UnixSocketBuilderExt for SocketBuilder {
+    fn reuse_port(&self, reuse: bool) -> io::Result<&Self> {
+        self.setopt(libc::SOL_SOCKET, libc::SO_REUSEPORT, reuse as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device(&self, interface: Option<&[u8]>) -> io::Result<&Self> {
+        let interface = interface.unwrap_or(&[]);
+        self.setopt_bytes(libc::SOL_SOCKET, SO_BINDTODEVICE, interface)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn bind_device_index(&self, index: Option<u32>) -> io::Result<&Self> {
+        self.setopt(libc::SOL_SOCKET, SO_BINDTOIFINDEX, index.unwrap_or(0) as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IP, IP_FREEBIND, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn freebind_v6(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IPV6, IPV6_FREEBIND, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IP, IP_TRANSPARENT, enable as c_int)
+            .map(|()| self)
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    fn transparent_v6(&self, enable: bool) -> io::Result<&Self> {
+        self.setopt(libc::IPPROTO_IPV6, IPV6_TRANSPARENT, enable as c_int)
+            .map(|()| self)
+    }
+}
+
+/// Linux-specific extensions for the standard `TcpStream`, `TcpListener`
+/// and `UdpSocket` types.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub trait UnixIpSocketExt {
+    /// Sets the value of the `SO_BINDTODEVICE` option on this socket.
+    ///
+    /// For more information about this option, see
+    /// [`UnixTcpBuilderExt::bind_device`][link].
+    ///
+    /// [link]: trait.UnixTcpBuilderExt.html#tymethod.bind_device
+    fn set_bind_device(&self, interface: Option<&[u8]>) -> io::Result<()>;
+
+    /// Gets the value of the `SO_BINDTODEVICE` option on this socket.
+    ///
+    /// This returns the name of the interface the socket is bound to, or
+    /// `None` if it is not bound to one.
+    fn bind_device(&self) -> io::Result<Option<Vec<u8>>>;
+
+    /// Sets the value of the `SO_BINDTOIFINDEX` option on this socket.
+    ///
+    /// For more information about this option, see
+    /// [`UnixTcpBuilderExt::bind_device_index`][link].
+    ///
+    /// [link]: trait.UnixTcpBuilderExt.html#tymethod.bind_device_index
+    fn set_bind_device_index(&self, index: Option<u32>) -> io::Result<()>;
+
+    /// Gets the value of the `SO_BINDTOIFINDEX` option on this socket.
+    ///
+    /// This returns the index of the interface the socket is bound to, or
+    /// `None` if it is not bound to one.
+    fn bind_device_index(&self) -> io::Result<Option<u32>>;
+
+    /// Sets the value of the `IP_FREEBIND` option on this socket.
+    ///
+    /// For more information about this option, see
+    /// [`UnixTcpBuilderExt::freebind`][link].
+    ///
+    /// [link]: trait.UnixTcpBuilderExt.html#tymethod.freebind
+    fn set_freebind(&self, enable: bool) -> io::Result<()>;
+
+    /// Gets the value of the `IP_FREEBIND` option on this socket.
+    fn freebind(&self) -> io::Result<bool>;
+
+    /// Sets the value of the `IPV6_FREEBIND` option on this socket.
+    ///
+    /// For more information about this option, see
+    /// [`UnixTcpBuilderExt::freebind_v6`][link].
+    ///
+    /// [link]: trait.UnixTcpBuilderExt.html#tymethod.freebind_v6
+    fn set_freebind_v6(&self, enable: bool) -> io::Result<()>;
+
+    /// Gets the value of the `IPV6_FREEBIND` option on this socket.
+    fn freebind_v6(&self) -> io::Result<bool>;
+
+    /// Sets the value of the `IP_TRANSPARENT` option on this socket.
+    ///
+    /// For more information about this option, see
+    /// [`UnixTcpBuilderExt::transparent`][link].
+    ///
+    /// [link]: trait.UnixTcpBuilderExt.html#tymethod.transparent
+    fn set_transparent(&self, enable: bool) -> io::Result<()>;
+
+    /// Gets the value of the `IP_TRANSPARENT` option on this socket.
+    fn transparent(&self) -> io::Result<bool>;
+
+    /// Sets the value of the `IPV6_TRANSPARENT` option on this socket.
+    ///
+    /// For more information about this option, see
+    /// [`UnixTcpBuilderExt::transparent_v6`][link].
+    ///
+    /// [link]: trait.UnixTcpBuilderExt.html#tymethod.transparent_v6
+    fn set_transparent_v6(&self, enable: bool) -> io::Result<()>;
+
+    /// Gets the value of the `IPV6_TRANSPARENT` option on this socket.
+    fn transparent_v6(&self) -> io::Result<bool>;
+}
+
+macro_rules! ip_socket_ext {
+    ($name:ty) => (
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        impl UnixIpSocketExt for $name {
+            fn set_bind_device(&self, interface: Option<&[u8]>) -> io::Result<()> {
+                set_bind_device(self.as_raw_fd(), interface)
+            }
+
+            fn bind_device(&self) -> io::Result<Option<Vec<u8>>> {
+                let mut buf = [0; IFNAMSIZ];
+                let len = try!(ext::getopt_bytes(self.as_raw_fd(), libc::SOL_SOCKET,
+                                                 SO_BINDTODEVICE, &mut buf));
+                let name = buf[..len].split(|&b| b == 0).next().unwrap_or(&[]);
+                Ok(if name.is_empty() {None} else {Some(name.to_vec())})
+            }
+
+            fn set_bind_device_index(&self, index: Option<u32>) -> io::Result<()> {
+                set_bind_device_index(self.as_raw_fd(), index)
+            }
+
+            fn bind_device_index(&self) -> io::Result<Option<u32>> {
+                ext::getopt::<c_int>(self.as_raw_fd(), libc::SOL_SOCKET,
+                                     SO_BINDTOIFINDEX).map(|i| {
+                    if i == 0 {None} else {Some(i as u32)}
+                })
+            }
+
+            fn set_freebind(&self, enable: bool) -> io::Result<()> {
+                ext::setopt(self.as_raw_fd(), libc::IPPROTO_IP, IP_FREEBIND,
+                            enable as c_int)
+            }
+
+            fn freebind(&self) -> io::Result<bool> {
+                ext::getopt::<c_int>(self.as_raw_fd(), libc::IPPROTO_IP,
+                                     IP_FREEBIND).map(|b| b != 0)
+            }
+
+            fn set_freebind_v6(&self, enable: bool) -> io::Result<()> {
+                ext::setopt(self.as_raw_fd(), libc::IPPROTO_IPV6, IPV6_FREEBIND,
+                            enable as c_int)
+            }
+
+            fn freebind_v6(&self) -> io::Result<bool> {
+                ext::getopt::<c_int>(self.as_raw_fd(), libc::IPPROTO_IPV6,
+                                     IPV6_FREEBIND).map(|b| b != 0)
+            }
+
+            fn set_transparent(&self, enable: bool) -> io::Result<()> {
+                ext::setopt(self.as_raw_fd(), libc::IPPROTO_IP, IP_TRANSPARENT,
+                            enable as c_int)
+            }
+
+            fn transparent(&self) -> io::Result<bool> {
+                ext::getopt::<c_int>(self.as_raw_fd(), libc::IPPROTO_IP,
+                                     IP_TRANSPARENT).map(|b| b != 0)
+            }
+
+            fn set_transparent_v6(&self, enable: bool) -> io::Result<()> {
+                ext::setopt(self.as_raw_fd(), libc::IPPROTO_IPV6,
+                            IPV6_TRANSPARENT, enable as c_int)
+            }
+
+            fn transparent_v6(&self) -> io::Result<bool> {
+                ext::getopt::<c_int>(self.as_raw_fd(), libc::IPPROTO_IPV6,
+                                     IPV6_TRANSPARENT).map(|b| b != 0)
+            }
+        }
+    )
+}
+
+ip_socket_ext!(::std::net::TcpStream);
+ip_socket_ext!(::std::net::TcpListener);
+ip_socket_ext!(::std::net::UdpSocket);
+
+/// Linux-specific extensions for the standard `TcpStream` type, for
+/// transparent proxies.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub trait UnixTcpStreamExt {
+    /// Gets the value of the `SO_ORIGINAL_DST` option on this socket.
+    ///
+    /// For a connection which was redirected to this socket by netfilter NAT,
+    /// such as with the `REDIRECT` iptables target, this returns the address
+    /// the peer originally connected to. IPv6 connections use
+    /// `IP6T_SO_ORIGINAL_DST`. Connections accepted through `TPROXY` and
+    /// `IP_TRANSPARENT` do not need this, as their `local_addr` is already
+    /// the original destination.
+    fn original_dst(&self) -> io::Result<SocketAddr>;
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl UnixTcpStreamExt for ::std::net::TcpStream {
+    fn original_dst(&self) -> io::Result<SocketAddr> {
+        let level = match sockaddr::unmap_v4(try!(self.local_addr())) {
+            SocketAddr::V4(..) => libc::IPPROTO_IP,
+            SocketAddr::V6(..) => libc::IPPROTO_IPV6,
+        };
+        let mut buf = vec![0; mem::size_of::<libc::sockaddr_storage>()];
+        let len = try!(ext::getopt_bytes(self.as_raw_fd(), level,
+                                         SO_ORIGINAL_DST, &mut buf));
+        let addr = unsafe {
+            SockAddr::from_raw_parts(buf.as_ptr() as *const _, len as socklen_t)
+        };
+        addr.as_socket_addr().ok_or(::Error::NotInet.into())
+    }
+}
+
+/// Linux-specific extensions for the standard `UdpSocket` type, for
+/// transparent proxies.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub trait UnixUdpSocketExt {
+    /// Sets the value of the `IP_RECVORIGDSTADDR` option on this socket, and
+    /// of `IPV6_RECVORIGDSTADDR` as well if it is an IPv6 socket.
+    ///
+    /// When enabled, each datagram received carries the address it was
+    /// originally sent to, which can be read with
+    /// `recv_from_original_dst`. With `TPROXY` this is the destination the
+    /// client intended, rather than the proxy's own address.
+    fn set_recv_original_dst(&self, enable: bool) -> io::Result<()>;
+
+    /// Gets the value of the `IP_RECVORIGDSTADDR` option on this socket, or
+    /// of `IPV6_RECVORIGDSTADDR` if it is an IPv6 socket.
+    fn recv_original_dst(&self) -> io::Result<bool>;
+
+    /// Receives a datagram into `buf`, returning the number of bytes read,
+    /// the address it came from, and the address it was originally sent to.
+    ///
+    /// The original destination is only available if
+    /// `set_recv_original_dst` was enabled when the datagram arrived;
+    /// otherwise `None` is returned.
+    fn recv_from_original_dst(&self, buf: &mut [u8])
+                              -> io::Result<(usize, SocketAddr, Option<SocketAddr>)>;
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl UnixUdpSocketExt for ::std::net::UdpSocket {
+    fn set_recv_original_dst(&self, enable: bool) -> io::Result<()> {
+        let fd = self.as_raw_fd();
+        try!(ext::setopt(fd, libc::IPPROTO_IP, IP_RECVORIGDSTADDR,
+                         enable as c_int));
+        if try!(self.local_addr()).is_ipv6() {
+            try!(ext::setopt(fd, libc::IPPROTO_IPV6, IPV6_RECVORIGDSTADDR,
+                             enable as c_int));
+        }
+        Ok(())
+    }
+
+    fn recv_original_dst(&self) -> io::Result<bool> {
+        let (level, name) = if try!(self.local_addr()).is_ipv6() {
+            (libc::IPPROTO_IPV6, IPV6_RECVORIGDSTADDR)
+        } else {
+            (libc::IPPROTO_IP, IP_RECVORIGDSTADDR)
+        };
+        ext::getopt::<c_int>(self.as_raw_fd(), level, name).map(|b| b != 0)
+    }
+
+    fn recv_from_original_dst(&self, buf: &mut [u8])
+                              -> io::Result<(usize, SocketAddr, Option<SocketAddr>)> {
+        let space = msg::cmsg_space(mem::size_of::<libc::sockaddr_in6>());
+        let mut control = msg::Control::with_space(space);
+        let (n, _, from) = try!(msg::recv_from(self.as_raw_fd(), buf,
+                                               &mut control, 0));
+        let from = try!(from.as_socket_addr().ok_or(::Error::NotInet));
+        let dst = control.iter().filter(|&(level, ty, _)| {
+            (level, ty) == (libc::IPPROTO_IP, IP_RECVORIGDSTADDR) ||
+                (level, ty) == (libc::IPPROTO_IPV6, IPV6_RECVORIGDSTADDR)
+        }).filter_map(|(_, _, data)| unsafe {
+            SockAddr::from_raw_parts(data.as_ptr() as *const _,
+                                     data.len() as socklen_t).as_socket_addr()
+        }).next();
+        Ok((n, from, dst))
+    }
+}
+
+/// A group of TCP listeners bound to the same address with `SO_REUSEPORT`,
+/// among which the kernel spreads incoming connections.
+///
+/// Each member is created from the template of a `TcpBuilder`, so it has
+/// every option set on the builder, and is then bound and listened in the
+/// same way as the others. The usual way to use a pool is to give each
+/// worker thread its own member, so that they accept connections without
+/// contending on a single listener.
+///
+/// This type is only available on Linux and Android. Other Unix platforms
+/// accept `SO_REUSEPORT` too, but there it only lets sockets share a port,
+/// and the kernel does not spread connections between them (FreeBSD offers
+/// that separately, as `SO_REUSEPORT_LB`), so a pool would not balance load.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::thread;
+/// use net2::TcpBuilder;
+/// use net2::unix::ReusePortPool;
+///
+/// let builder = TcpBuilder::new_v4().unwrap();
+/// builder.reuse_address(true).unwrap();
+///
+/// let mut pool = ReusePortPool::new(&builder, "0.0.0.0:8080", 4, 128).unwrap();
+/// for listener in pool.listeners() {
+///     let listener = listener.try_clone().unwrap();
+///     thread::spawn(move || {
+///         for stream in listener.incoming() {
+///             // ...
+///         }
+///     });
+/// }
+///
+/// // Scale down to three workers; the fourth's `incoming` stops
+/// pool.remove(3).unwrap();
+/// ```
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub struct ReusePortPool {
+    template: Template,
+    addr: SockAddr,
+    backlog: i32,
+    listeners: Vec<TcpListener>,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl ReusePortPool {
+    /// Creates a pool of `size` listeners from `builder`'s template, each
+    /// bound to `addr` with `SO_REUSEPORT` and listening with `backlog`.
+    ///
+    /// If `addr` resolves to several addresses, the one of the same family
+    /// as the builder is used, as with `AddrSelection::MatchFamily`. If its
+    /// port is 0, the first member is bound to a port of the kernel's
+    /// choosing and the rest of the pool shares it. The builder itself is
+    /// left untouched and may go on to be used for other sockets.
+    pub fn new<T>(builder: &TcpBuilder, addr: T, size: usize, backlog: i32)
+                  -> io::Result<ReusePortPool>
+        where T: ToSocketAddrs
+    {
+        let template = builder.template().borrow().clone();
+        let addr = try!(select::select(addr, AddrSelection::MatchFamily,
+                                       template.family()));
+        let mut pool = ReusePortPool {
+            addr: template.map(&addr.into()),
+            template: template,
+            backlog: backlog,
+            listeners: Vec::new(),
+        };
+        for _ in 0..size {
+            try!(pool.add());
+        }
+        Ok(pool)
+    }
+
+    /// Creates a new member of this pool, returning a reference to it.
+    ///
+    /// The kernel starts handing the new listener a share of the incoming
+    /// connections as soon as it is listening.
+    pub fn add(&mut self) -> io::Result<&TcpListener> {
+        let sock = try!(self.template.create_unbound(self.addr.family()));
+        try!(ext::setopt(sock.as_inner().raw(), libc::SOL_SOCKET,
+                         libc::SO_REUSEPORT, 1 as c_int));
+        try!(sock.bind(&self.addr));
+        try!(sock.listen(self.backlog));
+        let listener = sock.into_inner().into_tcp_listener();
+        if self.listeners.is_empty() {
+            // later members must share the port the kernel chose, if any
+            self.addr = SockAddr::from(try!(listener.local_addr()));
+        }
+        self.listeners.push(listener);
+        Ok(&self.listeners[self.listeners.len() - 1])
+    }
+
+    /// Removes the member at `index` from this pool, so that the kernel
+    /// stops handing it new connections.
+    ///
+    /// The listener is shut down rather than only closed, so that a worker
+    /// still accepting on a clone of it gets an error instead of blocking
+    /// forever. Connections which it had queued but not yet accepted are
+    /// reset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> io::Result<()> {
+        let listener = self.listeners.remove(index);
+        ::cvt(unsafe {
+            libc::shutdown(listener.as_raw_fd(), libc::SHUT_RD)
+        }).map(|_| ())
+    }
+
+    /// Returns the address that the members of this pool are bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr.as_socket_addr().unwrap()
+    }
+
+    /// Returns the members of this pool, in the order they were added.
+    pub fn listeners(&self) -> &[TcpListener] {
+        &self.listeners
+    }
+
+    /// Returns the number of members in this pool.
+    pub fn len(&self) -> usize {
+        self.listeners.len()
+    }
+
+    /// Returns whether this pool has no members.
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    /// Consumes this pool, returning its members.
+    pub fn into_listeners(self) -> Vec<TcpListener> {
+        self.listeners
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl IntoIterator for ReusePortPool {
+    type Item = TcpListener;
+    type IntoIter = ::std::vec::IntoIter<TcpListener>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.listeners.into_iter()
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl fmt::Debug for ReusePortPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ReusePortPool {{ addr: {:?}, listeners: {:?} }}",
+               self.addr, self.listeners)
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_bind_device(sock: c_int, interface: Option<&[u8]>) -> io::Result<()> {
+    ext::setopt_bytes(sock, libc::SOL_SOCKET, SO_BINDTODEVICE,
+                      interface.unwrap_or(&[]))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_bind_device_index(sock: c_int, index: Option<u32>) -> io::Result<()> {
+    ext::setopt(sock, libc::SOL_SOCKET, SO_BINDTOIFINDEX,
+                index.unwrap_or(0) as c_int)
+}
+
+macro_rules! unix_builder {
+    ($name:ident $(, $field:ident: $init:expr)*) => (
+        impl $name {
+            /// Sets the value of the `SO_SNDBUF` option on this socket.
+            ///
+            /// This is the size of the buffer the kernel allocates to hold
+            /// data which has been written to the socket but not yet sent.
+            pub fn send_buffer_size(&self, size: usize) -> io::Result<&Self> {
+                let sock = try!(self.raw_fd());
+                ext::setopt(sock, libc::SOL_SOCKET, libc::SO_SNDBUF,
+                            size as c_int).map(|()| self)
+            }
+
+            /// Sets the value of the `SO_RCVBUF` option on this socket.
+            ///
+            /// This is the size of the buffer the kernel allocates to hold
+            /// data which has been received by the socket but not yet read.
+            pub fn recv_buffer_size(&self, size: usize) -> io::Result<&Self> {
+                let sock = try!(self.raw_fd());
+                ext::setopt(sock, libc::SOL_SOCKET, libc::SO_RCVBUF,
+                            size as c_int).map(|()| self)
+            }
+
+            /// Sets the `SO_RCVTIMEO` option for this socket.
+            ///
+            /// This is the same as
+            /// [`TcpStreamExt::set_read_timeout_ms`][other].
+            ///
+            /// [other]: ../trait.TcpStreamExt.html#tymethod.set_read_timeout_ms
+            pub fn read_timeout_ms(&self, dur: Option<u32>) -> io::Result<&Self> {
+                let sock = try!(self.raw_fd());
+                ext::setopt(sock, libc::SOL_SOCKET, libc::SO_RCVTIMEO,
+                            ext::ms2timeout(dur)).map(|()| self)
+            }
+
+            /// Sets the `SO_SNDTIMEO` option for this socket.
+            ///
+            /// This is the same as
+            /// [`TcpStreamExt::set_write_timeout_ms`][other].
+            ///
+            /// [other]: ../trait.TcpStreamExt.html#tymethod.set_write_timeout_ms
+            pub fn write_timeout_ms(&self, dur: Option<u32>) -> io::Result<&Self> {
+                let sock = try!(self.raw_fd());
+                ext::setopt(sock, libc::SOL_SOCKET, libc::SO_SNDTIMEO,
+                            ext::ms2timeout(dur)).map(|()| self)
+            }
+
+            /// Returns the address that this socket is bound to.
+            ///
+            /// This function directly corresponds to the getsockname(2)
+            /// function on Unix, and will report abstract and autobound names
+            /// as well as filesystem paths.
+            pub fn local_addr(&self) -> io::Result<UnixAddr> {
+                local_unix_addr(try!(self.raw_fd()))
+            }
+
+            /// Binds this socket to an address chosen by the kernel.
+            ///
+            /// On Linux this assigns the socket a unique name in the abstract
+            /// namespace, which can be retrieved with `local_addr`. This is the
+            /// same as binding to `UnixAddr::Unnamed`.
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            pub fn autobind(&self) -> io::Result<&Self> {
+                let addr = try!(SockAddr::from_unix(&UnixAddr::Unnamed));
+                self.bind_sock_addr(&addr).map(|()| self)
+            }
+
+            /// Get the value of the `SO_ERROR` option on this socket.
+            ///
+            /// This will retrieve the stored error in the underlying socket,
+            /// clearing the field in the process. This can be useful for
+            /// checking errors between calls.
+            pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+                let sock = try!(self.raw_fd());
+                ext::getopt(sock, libc::SOL_SOCKET, libc::SO_ERROR)
+                    .map(ext::int2err)
+            }
+
+            /// Returns the raw file descriptor of this builder's socket, or
+            /// `None` if the socket has been finished by `bind`, `listen`,
+            /// `connect` or similar.
+            pub fn as_raw_fd_opt(&self) -> Option<RawFd> {
+                self.socket.borrow().as_ref().map(|s| s.as_inner().raw())
+            }
+
+            fn raw_fd(&self) -> io::Result<c_int> {
+                self.as_raw_fd_opt().ok_or(::Error::BuilderFinished.into())
+            }
+
+            fn bind_sock_addr(&self, addr: &SockAddr) -> io::Result<()> {
+                self.with_socket(|sock| sock.bind(addr))
+            }
+
+            fn take_socket(&self) -> io::Result<sys::Socket> {
+                self.socket.borrow_mut().take().map(|s| s.into_inner())
+                    .ok_or(::Error::SocketConsumed.into())
+            }
+
+            fn with_socket<F>(&self, f: F) -> io::Result<()>
+                where F: FnOnce(&Socket) -> io::Result<()>
+            {
+                match *self.socket.borrow() {
+                    Some(ref s) => f(s),
+                    None => Err(::Error::BuilderFinished.into()),
+                }
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match *self.socket.borrow() {
+                    Some(ref s) => {
+                        write!(f, "{} {{ socket: {:?} }}", stringify!($name), s)
+                    }
+                    None => write!(f, "{} {{ socket: None }}", stringify!($name)),
+                }
+            }
+        }
+
+        impl AsInner for $name {
+            type Inner = RefCell<Option<Socket>>;
+            fn as_inner(&self) -> &RefCell<Option<Socket>> { &self.socket }
+        }
+
+        impl FromInner for $name {
+            type Inner = Socket;
+            fn from_inner(sock: Socket) -> $name {
+                $name { socket: RefCell::new(Some(sock)) $(, $field: $init)* }
+            }
+        }
+
+        impl FromRawFd for $name {
+            unsafe fn from_raw_fd(fd: c_int) -> $name {
+                let sock = sys::Socket::from_inner(fd);
+                $name::from_inner(Socket::from_inner(sock))
+            }
+        }
+    )
+}
+
+/// An "in progress" Unix domain stream socket which has not yet been
+/// connected.
+///
+/// Allows configuration of a socket before it is connected, finishing into a
+/// `std::os::unix::net::UnixStream`.
+pub struct UnixStreamBuilder {
+    socket: RefCell<Option<Socket>>,
+}
+
+unix_builder!(UnixStreamBuilder);
+
+impl UnixStreamBuilder {
+    /// Constructs a new UnixStreamBuilder with the `AF_UNIX` domain, the
+    /// `SOCK_STREAM` type, and with a protocol argument of 0.
+    pub fn new() -> io::Result<UnixStreamBuilder> {
+        Socket::new(libc::AF_UNIX, libc::SOCK_STREAM, 0).map(FromInner::from_inner)
+    }
+
+    /// Binds this socket to the specified path.
+    ///
+    /// This function directly corresponds to the bind(2) function on Unix.
+    pub fn bind<P: AsRef<Path>>(&self, path: P) -> io::Result<&Self> {
+        self.bind_addr(&UnixAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Binds this socket to the specified address.
+    ///
+    /// Unlike `bind`, this can also bind to a name in the Linux abstract
+    /// namespace, or autobind if `UnixAddr::Unnamed` is given.
+    pub fn bind_addr<A: ToSockAddr + ?Sized>(&self, addr: &A) -> io::Result<&Self> {
+        let addr = try!(addr.to_sock_addr());
+        self.bind_sock_addr(&addr).map(|()| self)
+    }
+
+    /// Initiate a connection on this socket to the specified path.
+    ///
+    /// This function directly corresponds to the connect(2) function on Unix.
+    ///
+    /// An error will be returned if `connect` has already been called on this
+    /// builder.
+    pub fn connect<P: AsRef<Path>>(&self, path: P) -> io::Result<UnixStream> {
+        self.connect_addr(&UnixAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Initiate a connection on this socket to the specified address.
+    ///
+    /// Unlike `connect`, this can also connect to a socket bound to a name in
+    /// the Linux abstract namespace.
+    pub fn connect_addr<A: ToSockAddr + ?Sized>(&self, addr: &A)
+                                                -> io::Result<UnixStream> {
+        self.with_socket(|sock| {
+            sock.connect(&try!(addr.to_sock_addr()))
+        }).and_then(|()| {
+            self.to_unix_stream()
+        })
+    }
+
+    /// Converts this builder into a `UnixStream`
+    ///
+    /// This function will consume the internal socket and return it re-wrapped
+    /// as a `UnixStream`. An error will be returned if the internal socket has
+    /// already been consumed from a successful call to `connect`.
+    pub fn to_unix_stream(&self) -> io::Result<UnixStream> {
+        self.take_socket().map(|s| s.into_unix_stream())
+    }
+}
+
+/// An "in progress" Unix domain stream socket which has not yet been listened.
+///
+/// Allows configuration of a socket before it is listened, finishing into a
+/// `std::os::unix::net::UnixListener`.
+///
+/// In addition to socket options, this builder can manage the lifecycle of the
+/// socket file when bound to a path: removing a stale file left behind by a
+/// process which exited without cleaning up, setting the file's mode and
+/// owner before any client is able to connect, and removing the file again
+/// when the listener is dropped.
+pub struct UnixListenerBuilder {
+    socket: RefCell<Option<Socket>>,
+    file: RefCell<SocketFile>,
+}
+
+#[derive(Default)]
+struct SocketFile {
+    path: Option<PathBuf>,
+    remove_stale: bool,
+    mode: Option<u32>,
+    owner: Option<(Option<u32>, Option<u32>)>,
+}
+
+unix_builder!(UnixListenerBuilder, file: RefCell::new(SocketFile::default()));
+
+impl UnixListenerBuilder {
+    /// Constructs a new UnixListenerBuilder with the `AF_UNIX` domain, the
+    /// `SOCK_STREAM` type, and with a protocol argument of 0.
+    pub fn new() -> io::Result<UnixListenerBuilder> {
+        Socket::new(libc::AF_UNIX, libc::SOCK_STREAM, 0).map(FromInner::from_inner)
+    }
+
+    /// Binds this socket to the specified path.
+    ///
+    /// This function directly corresponds to the bind(2) function on Unix.
+    pub fn bind<P: AsRef<Path>>(&self, path: P) -> io::Result<&Self> {
+        self.bind_addr(&UnixAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Binds this socket to the specified address.
+    ///
+    /// Unlike `bind`, this can also bind to a name in the Linux abstract
+    /// namespace, or autobind if `UnixAddr::Unnamed` is given.
+    pub fn bind_addr<A: ToSockAddr + ?Sized>(&self, addr: &A) -> io::Result<&Self> {
+        let addr = try!(addr.to_sock_addr());
+        let path = match addr.as_unix() {
+            Some(UnixAddr::Pathname(path)) => Some(path),
+            _ => None,
+        };
+        if let Some(ref path) = path {
+            if self.file.borrow().remove_stale {
+                try!(remove_stale_socket(path));
+            }
+        }
+        match path {
+            Some(ref path) => try!(self.bind_file(&addr, path)),
+            None => try!(self.bind_sock_addr(&addr)),
+        }
+        if path.is_some() {
+            self.file.borrow_mut().path = path;
+        }
+        Ok(self)
+    }
+
+    /// Configures whether a stale socket file is removed before binding.
+    ///
+    /// When enabled and `bind` is given a path which already exists as a
+    /// socket, a connection to it is attempted first. Only if that connection
+    /// is refused, meaning no process is listening on it any more, is the file
+    /// removed. Files which are not sockets and sockets which are still in use
+    /// are left alone, and `bind` will fail with `AddrInUse` as usual.
+    ///
+    /// This option is disabled by default.
+    pub fn remove_stale(&self, remove: bool) -> io::Result<&Self> {
+        self.file.borrow_mut().remove_stale = remove;
+        Ok(self)
+    }
+
+    /// Sets the permissions of the socket file, such as `0o660`.
+    ///
+    /// The file is created with this mode by `bind`, so it is never
+    /// accessible with its default permissions. This works by changing the
+    /// process's umask for the duration of the bind, which also affects files
+    /// created by other threads at the same time.
+    ///
+    /// This must be called before `bind`, otherwise an error is returned.
+    pub fn mode(&self, mode: u32) -> io::Result<&Self> {
+        try!(self.check_file_unbound());
+        self.file.borrow_mut().mode = Some(mode);
+        Ok(self)
+    }
+
+    /// Sets the owning user and group of the socket file.
+    ///
+    /// A value of `None` leaves the respective id unchanged. The ownership is
+    /// changed by `bind` right after creating the file, before the socket is
+    /// listened, and a symbolic link put in place of the file is not
+    /// followed. Changing the owning user generally requires elevated
+    /// privileges.
+    ///
+    /// This must be called before `bind`, otherwise an error is returned.
+    pub fn owner(&self, uid: Option<u32>, gid: Option<u32>) -> io::Result<&Self> {
+        try!(self.check_file_unbound());
+        self.file.borrow_mut().owner = Some((uid, gid));
+        Ok(self)
+    }
+
+    /// Mark a socket as ready to accept incoming connection requests using
+    /// accept()
+    ///
+    /// This function directly corresponds to the listen(2) function on Unix.
+    ///
+    /// An error will be returned if `listen` has already been called on this
+    /// builder.
+    pub fn listen(&self, backlog: i32) -> io::Result<UnixListener> {
+        self.with_socket(|sock| sock.listen(backlog)).and_then(|()| {
+            self.to_unix_listener()
+        })
+    }
+
+    /// Mark a socket as ready to accept incoming connection requests, removing
+    /// the socket file when the returned listener is dropped.
+    ///
+    /// This is the same as `listen`, except that the listener is wrapped in a
+    /// `UnixListenerGuard`. The file is only removed if it still refers to
+    /// the socket created by this builder, so a file which has since been
+    /// replaced by another process is left alone.
+    pub fn listen_guarded(&self, backlog: i32) -> io::Result<UnixListenerGuard> {
+        let listener = try!(self.listen(backlog));
+        let path = self.file.borrow().path.clone();
+        let id = match path {
+            Some(ref path) => Some(try!(file_id(path))),
+            None => None,
+        };
+        Ok(UnixListenerGuard { listener: listener, path: path, id: id })
+    }
+
+    /// Converts this builder into a `UnixListener`
+    ///
+    /// This function will consume the internal socket and return it re-wrapped
+    /// as a `UnixListener`. An error will be returned if the internal socket
+    /// has already been consumed from a successful call to `listen`.
+    pub fn to_unix_listener(&self) -> io::Result<UnixListener> {
+        self.take_socket().map(|s| s.into_unix_listener())
+    }
+
+    fn bind_file(&self, addr: &SockAddr, path: &Path) -> io::Result<()> {
+        let (mode, owner) = {
+            let file = self.file.borrow();
+            (file.mode, file.owner)
+        };
+        // The socket file takes its permissions from the umask, so setting
+        // it around the bind creates the file with the right mode in the
+        // first place, rather than changing it by path afterwards.
+        let res = match mode {
+            Some(mode) => unsafe {
+                let old = umask(!mode as libc::mode_t & 0o777);
+                let res = self.bind_sock_addr(addr);
+                umask(old);
+                res
+            },
+            None => self.bind_sock_addr(addr),
+        };
+        try!(res);
+        if let Some((uid, gid)) = owner {
+            let path = try!(CString::new(path.as_os_str().as_bytes()));
+            let uid = uid.map(|u| u as libc::uid_t).unwrap_or(!0);
+            let gid = gid.map(|g| g as libc::gid_t).unwrap_or(!0);
+            try!(::cvt(unsafe { lchown(path.as_ptr(), uid, gid) }));
+        }
+        Ok(())
+    }
+
+    fn check_file_unbound(&self) -> io::Result<()> {
+        if self.file.borrow().path.is_some() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "the socket file must be configured before binding"))
+        }
+        Ok(())
+    }
+}
+
+/// A `UnixListener` which removes its socket file when dropped.
+///
+/// Instances of this type are created by
+/// [`UnixListenerBuilder::listen_guarded`][link], and dereference to the
+/// underlying `UnixListener`.
+///
+/// [link]: struct.UnixListenerBuilder.html#method.listen_guarded
+#[derive(Debug)]
+pub struct UnixListenerGuard {
+    listener: UnixListener,
+    path: Option<PathBuf>,
+    id: Option<(u64, u64)>,
+}
+
+impl UnixListenerGuard {
+    /// Returns the path of the socket file which will be removed on drop, if
+    /// the listener is bound to a path.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(|p| &**p)
+    }
+
+    /// Consumes this guard, returning the listener without removing the
+    /// socket file.
+    pub fn into_inner(mut self) -> UnixListener {
+        self.path = None;
+        let listener = unsafe { ptr::read(&self.listener) };
+        mem::forget(self);
+        listener
+    }
+}
+
+impl Deref for UnixListenerGuard {
+    type Target = UnixListener;
+    fn deref(&self) -> &UnixListener { &self.listener }
+}
+
+impl Drop for UnixListenerGuard {
+    fn drop(&mut self) {
+        if let (Some(ref path), Some(id)) = (self.path.take(), self.id) {
+            if file_id(path).ok() == Some(id) {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn file_id(path: &Path) -> io::Result<(u64, u64)> {
+    fs::symlink_metadata(path).map(|m| (m.dev(), m.ino()))
+}
+
+fn remove_stale_socket(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(ref meta) if meta.file_type().is_socket() => {}
+        Ok(..) => return Ok(()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    }
+    let probe = try!(UnixStreamBuilder::new());
+    match probe.connect(path) {
+        Ok(..) => Ok(()),
+        Err(ref e) if e.kind() == io::ErrorKind::ConnectionRefused => {
+            match fs::remove_file(path) {
+                Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                other => other,
+            }
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// An "in progress" Unix domain datagram socket which has not yet been bound.
+///
+/// Allows configuration of a socket before the socket is bound, finishing into
+/// a `std::os::unix::net::UnixDatagram`.
+pub struct UnixDatagramBuilder {
+    socket: RefCell<Option<Socket>>,
+}
+
+unix_builder!(UnixDatagramBuilder);
+
+impl UnixDatagramBuilder {
+    /// Constructs a new UnixDatagramBuilder with the `AF_UNIX` domain, the
+    /// `SOCK_DGRAM` type, and with a protocol argument of 0.
+    pub fn new() -> io::Result<UnixDatagramBuilder> {
+        Socket::new(libc::AF_UNIX, libc::SOCK_DGRAM, 0).map(FromInner::from_inner)
+    }
+
+    /// Binds this socket to the specified path.
+    ///
+    /// This function directly corresponds to the bind(2) function on Unix.
+    ///
+    /// Like `UdpBuilder::bind`, the internal socket is consumed and returned
+    /// as a `UnixDatagram`, so all configuration must happen beforehand.
+    pub fn bind<P: AsRef<Path>>(&self, path: P) -> io::Result<UnixDatagram> {
+        self.bind_addr(&UnixAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Binds this socket to the specified address.
+    ///
+    /// Unlike `bind`, this can also bind to a name in the Linux abstract
+    /// namespace, or autobind if `UnixAddr::Unnamed` is given. The internal
+    /// socket is consumed and returned as a `UnixDatagram`.
+    pub fn bind_addr<A: ToSockAddr + ?Sized>(&self, addr: &A)
+                                             -> io::Result<UnixDatagram> {
+        let addr = try!(addr.to_sock_addr());
+        try!(self.bind_sock_addr(&addr));
+        self.to_unix_datagram()
+    }
+
+    /// Set value for the `SO_PASSCRED` option on this socket.
+    ///
+    /// When enabled, the sending process' credentials are attached to every
+    /// message received on this socket in an `SCM_CREDENTIALS` control
+    /// message. This option is only available on Linux.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    pub fn pass_credentials(&self, pass: bool) -> io::Result<&Self> {
+        let sock = try!(self.raw_fd());
+        ext::setopt(sock, libc::SOL_SOCKET, SO_PASSCRED,
+                    pass as c_int).map(|()| self)
+    }
+
+    /// Converts this builder into a `UnixDatagram`
+    ///
+    /// This function will consume the internal socket and return it re-wrapped
+    /// as a `UnixDatagram`. The socket does not need to be bound, in which
+    /// case it can only be used to send messages. An error will be returned if
+    /// the internal socket has already been consumed from a successful call
+    /// to `bind`.
+    pub fn to_unix_datagram(&self) -> io::Result<UnixDatagram> {
+        self.take_socket().map(|s| s.into_unix_datagram())
+    }
+}
+
+/// An "in progress" Unix domain sequenced-packet socket which has not yet been
+/// connected.
+///
+/// Allows configuration of a socket before it is connected, finishing into a
+/// `UnixSeqpacket`.
+pub struct UnixSeqpacketBuilder {
+    socket: RefCell<Option<Socket>>,
+}
+
+unix_builder!(UnixSeqpacketBuilder);
+
+impl UnixSeqpacketBuilder {
+    /// Constructs a new UnixSeqpacketBuilder with the `AF_UNIX` domain, the
+    /// `SOCK_SEQPACKET` type, and with a protocol argument of 0.
+    pub fn new() -> io::Result<UnixSeqpacketBuilder> {
+        Socket::new(libc::AF_UNIX, SOCK_SEQPACKET, 0).map(FromInner::from_inner)
+    }
+
+    /// Binds this socket to the specified path.
+    ///
+    /// This function directly corresponds to the bind(2) function on Unix.
+    pub fn bind<P: AsRef<Path>>(&self, path: P) -> io::Result<&Self> {
+        self.bind_addr(&UnixAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Binds this socket to the specified address.
+    ///
+    /// This is the same as [`UnixStreamBuilder::bind_addr`][other].
+    ///
+    /// [other]: struct.UnixStreamBuilder.html#method.bind_addr
+    pub fn bind_addr<A: ToSockAddr + ?Sized>(&self, addr: &A) -> io::Result<&Self> {
+        let addr = try!(addr.to_sock_addr());
+        self.bind_sock_addr(&addr).map(|()| self)
+    }
+
+    /// Initiate a connection on this socket to the specified path.
+    ///
+    /// This function directly corresponds to the connect(2) function on Unix.
+    ///
+    /// An error will be returned if `connect` has already been called on this
+    /// builder.
+    pub fn connect<P: AsRef<Path>>(&self, path: P) -> io::Result<UnixSeqpacket> {
+        self.connect_addr(&UnixAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Initiate a connection on this socket to the specified address.
+    ///
+    /// This is the same as [`UnixStreamBuilder::connect_addr`][other].
+    ///
+    /// [other]: struct.UnixStreamBuilder.html#method.connect_addr
+    pub fn connect_addr<A: ToSockAddr + ?Sized>(&self, addr: &A)
+                                                -> io::Result<UnixSeqpacket> {
+        self.with_socket(|sock| {
+            sock.connect(&try!(addr.to_sock_addr()))
+        }).and_then(|()| {
+            self.to_unix_seqpacket()
+        })
+    }
+
+    /// Converts this builder into a `UnixSeqpacket`
+    ///
+    /// This function will consume the internal socket and return it re-wrapped
+    /// as a `UnixSeqpacket`. An error will be returned if the internal socket
+    /// has already been consumed from a successful call to `connect`.
+    pub fn to_unix_seqpacket(&self) -> io::Result<UnixSeqpacket> {
+        self.take_socket().map(|s| UnixSeqpacket::from_inner(Socket::from_inner(s)))
+    }
+}
+
+/// An "in progress" Unix domain sequenced-packet socket which has not yet been
+/// listened.
+///
+/// Allows configuration of a socket before it is listened, finishing into a
+/// `UnixSeqpacketListener`.
+pub struct UnixSeqpacketListenerBuilder {
+    socket: RefCell<Option<Socket>>,
+}
+
+unix_builder!(UnixSeqpacketListenerBuilder);
+
+impl UnixSeqpacketListenerBuilder {
+    /// Constructs a new UnixSeqpacketListenerBuilder with the `AF_UNIX`
+    /// domain, the `SOCK_SEQPACKET` type, and with a protocol argument of 0.
+    pub fn new() -> io::Result<UnixSeqpacketListenerBuilder> {
+        Socket::new(libc::AF_UNIX, SOCK_SEQPACKET, 0).map(FromInner::from_inner)
+    }
+
+    /// Binds this socket to the specified path.
+    ///
+    /// This function directly corresponds to the bind(2) function on Unix.
+    pub fn bind<P: AsRef<Path>>(&self, path: P) -> io::Result<&Self> {
+        self.bind_addr(&UnixAddr::Pathname(path.as_ref().to_path_buf()))
+    }
+
+    /// Binds this socket to the specified address.
+    ///
+    /// This is the same as [`UnixStreamBuilder::bind_addr`][other].
+    ///
+    /// [other]: struct.UnixStreamBuilder.html#method.bind_addr
+    pub fn bind_addr<A: ToSockAddr + ?Sized>(&self, addr: &A) -> io::Result<&Self> {
+        let addr = try!(addr.to_sock_addr());
+        self.bind_sock_addr(&addr).map(|()| self)
+    }
+
+    /// Mark a socket as ready to accept incoming connection requests using
+    /// accept()
+    ///
+    /// This function directly corresponds to the listen(2) function on Unix.
+    ///
+    /// An error will be returned if `listen` has already been called on this
+    /// builder.
+    pub fn listen(&self, backlog: i32) -> io::Result<UnixSeqpacketListener> {
+        self.with_socket(|sock| {
+            sock.listen(backlog)
+        }).and_then(|()| {
+            self.to_unix_seqpacket_listener()
+        })
+    }
+
+    /// Converts this builder into a `UnixSeqpacketListener`
+    ///
+    /// This function will consume the internal socket and return it re-wrapped
+    /// as a `UnixSeqpacketListener`. An error will be returned if the internal
+    /// socket has already been consumed from a successful call to `listen`.
+    pub fn to_unix_seqpacket_listener(&self) -> io::Result<UnixSeqpacketListener> {
+        self.take_socket().map(|s| {
+            UnixSeqpacketListener::from_inner(Socket::from_inner(s))
+        })
+    }
+}
+
+/// A connected Unix domain socket of the `SOCK_SEQPACKET` type.
+///
+/// Sequenced-packet sockets are reliable and ordered like streams, but
+/// preserve message boundaries: every `send` transmits exactly one message and
+/// every `recv` reads at most one. If a message is larger than the buffer
+/// passed to `recv` the excess is discarded.
+pub struct UnixSeqpacket {
+    inner: Socket,
+}
+
+impl UnixSeqpacket {
+    /// Sends a single message on this socket, returning the number of bytes
+    /// written.
+    ///
+    /// This function directly corresponds to the send(2) function on Unix.
+    pub fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        let ret = try!(::cvt(unsafe {
+            libc::send(self.as_raw_fd(), buf.as_ptr() as *const _,
+                       buf.len() as libc::size_t, MSG_NOSIGNAL)
+        }));
+        Ok(ret as usize)
+    }
+
+    /// Receives a single message from this socket, returning the number of
+    /// bytes read.
+    ///
+    /// A return value of 0 indicates that the peer has shut down the
+    /// connection. This function directly corresponds to the recv(2) function
+    /// on Unix.
+    pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let ret = try!(::cvt(unsafe {
+            libc::recv(self.as_raw_fd(), buf.as_mut_ptr() as *mut _,
+                       buf.len() as libc::size_t, 0)
+        }));
+        Ok(ret as usize)
+    }
+
+    /// Returns the address that this socket is bound to.
+    pub fn local_addr(&self) -> io::Result<UnixAddr> {
+        local_unix_addr(self.as_raw_fd())
+    }
+
+    /// Returns the address of the peer this socket is connected to.
+    pub fn peer_addr(&self) -> io::Result<UnixAddr> {
+        peer_unix_addr(self.as_raw_fd())
+    }
+
+    /// Shuts down the read, write, or both halves of this connection.
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        let how = match how {
+            Shutdown::Read => libc::SHUT_RD,
+            Shutdown::Write => libc::SHUT_WR,
+            Shutdown::Both => libc::SHUT_RDWR,
+        };
+        ::cvt(unsafe { libc::shutdown(self.as_raw_fd(), how) }).map(|_| ())
+    }
+
+    /// Moves this socket into or out of nonblocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        ext::set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    /// Get the value of the `SO_ERROR` option on this socket.
+    ///
+    /// This will retrieve the stored error in the underlying socket, clearing
+    /// the field in the process. This can be useful for checking errors between
+    /// calls.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        ext::getopt(self.as_raw_fd(), libc::SOL_SOCKET, libc::SO_ERROR)
+            .map(ext::int2err)
+    }
+}
+
+/// A Unix domain socket of the `SOCK_SEQPACKET` type listening for
+/// connections.
+pub struct UnixSeqpacketListener {
+    inner: Socket,
+}
+
+impl UnixSeqpacketListener {
+    /// Accepts a new incoming connection to this listener.
+    ///
+    /// This function will block the calling thread until a new connection is
+    /// established, returning the connected socket and the address of the
+    /// peer.
+    pub fn accept(&self) -> io::Result<(UnixSeqpacket, UnixAddr)> {
+        let mut sock = None;
+        let addr = try!(sockaddr::with_storage(|addr, len| {
+            sock = Some(try!(self.inner.as_inner().accept(addr, len, false)));
+            Ok(())
+        }));
+        let sock = Socket::from_inner(sock.unwrap());
+        Ok((UnixSeqpacket::from_inner(sock),
+            addr.as_unix().unwrap_or(UnixAddr::Unnamed)))
     }
+
+    /// Returns the address that this listener is bound to.
+    pub fn local_addr(&self) -> io::Result<UnixAddr> {
+        local_unix_addr(self.as_raw_fd())
+    }
+
+    /// Moves this listener into or out of nonblocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        ext::set_nonblocking(self.as_raw_fd(), nonblocking)
+    }
+
+    /// Get the value of the `SO_ERROR` option on this socket.
+    ///
+    /// This will retrieve the stored error in the underlying socket, clearing
+    /// the field in the process. This can be useful for checking errors between
+    /// calls.
+    pub fn take_error(&self) -> io::Result<Option<io::Error>> {
+        ext::getopt(self.as_raw_fd(), libc::SOL_SOCKET, libc::SO_ERROR)
+            .map(ext::int2err)
+    }
+}
+
+macro_rules! unix_socket {
+    ($name:ident) => (
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{} {{ socket: {:?} }}", stringify!($name), self.inner)
+            }
+        }
+
+        impl FromInner for $name {
+            type Inner = Socket;
+            fn from_inner(sock: Socket) -> $name {
+                $name { inner: sock }
+            }
+        }
+
+        impl FromRawFd for $name {
+            unsafe fn from_raw_fd(fd: c_int) -> $name {
+                let sock = sys::Socket::from_inner(fd);
+                $name::from_inner(Socket::from_inner(sock))
+            }
+        }
+
+        impl AsRawFd for $name {
+            fn as_raw_fd(&self) -> c_int {
+                self.inner.as_inner().raw()
+            }
+        }
+
+        impl IntoRawFd for $name {
+            fn into_raw_fd(self) -> c_int {
+                self.inner.into_inner().into_fd()
+            }
+        }
+    )
+}
+
+unix_socket!(UnixSeqpacket);
+unix_socket!(UnixSeqpacketListener);
+
+/// An address associated with a Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum UnixAddr {
+    /// The socket is not bound to any address.
+    ///
+    /// Binding a socket to this address on Linux will autobind it to a unique
+    /// name in the abstract namespace chosen by the kernel.
+    Unnamed,
+    /// The socket is bound to a path on the filesystem.
+    Pathname(PathBuf),
+    /// The socket is bound to a name in the Linux abstract namespace.
+    ///
+    /// The name does not include the leading null byte, and is not null
+    /// terminated. Abstract names are only supported on Linux.
+    Abstract(Vec<u8>),
+}
+
+impl UnixAddr {
+    /// Creates an address for the given name in the Linux abstract namespace.
+    pub fn abstract_name<N: AsRef<[u8]>>(name: N) -> UnixAddr {
+        UnixAddr::Abstract(name.as_ref().to_vec())
+    }
+}
+
+impl<'a> From<&'a Path> for UnixAddr {
+    fn from(path: &'a Path) -> UnixAddr {
+        UnixAddr::Pathname(path.to_path_buf())
+    }
+}
+
+impl From<PathBuf> for UnixAddr {
+    fn from(path: PathBuf) -> UnixAddr {
+        UnixAddr::Pathname(path)
+    }
+}
+
+/// Unix-specific extensions for the Unix domain socket types in
+/// `std::os::unix::net`.
+pub trait UnixSocketExt {
+    /// Returns the address that this socket is bound to.
+    ///
+    /// Unlike the `local_addr` methods in the standard library, this will
+    /// report names in the Linux abstract namespace, including those chosen
+    /// by the kernel when autobinding.
+    fn local_unix_addr(&self) -> io::Result<UnixAddr>;
+}
+
+impl UnixSocketExt for UnixStream {
+    fn local_unix_addr(&self) -> io::Result<UnixAddr> {
+        local_unix_addr(self.as_sock())
+    }
+}
+
+impl UnixSocketExt for UnixListener {
+    fn local_unix_addr(&self) -> io::Result<UnixAddr> {
+        local_unix_addr(self.as_sock())
+    }
+}
+
+impl UnixSocketExt for UnixDatagram {
+    fn local_unix_addr(&self) -> io::Result<UnixAddr> {
+        local_unix_addr(self.as_sock())
+    }
+}
+
+/// Extension methods for `UnixDatagram` taking `SockAddr` addresses.
+///
+/// Unlike `send_to` and `recv_from` in the standard library these can name
+/// sockets in the Linux abstract namespace.
+pub trait UnixDatagramExt {
+    /// Sends data on the socket to the given address, returning the number
+    /// of bytes written.
+    fn send_to_addr<A: ToSockAddr + ?Sized>(&self, buf: &[u8], addr: &A)
+                                            -> io::Result<usize>;
+
+    /// Receives data from the socket, returning the number of bytes read and
+    /// the address of the socket it came from.
+    fn recv_from_addr(&self, buf: &mut [u8]) -> io::Result<(usize, SockAddr)>;
+}
+
+impl UnixDatagramExt for UnixDatagram {
+    fn send_to_addr<A: ToSockAddr + ?Sized>(&self, buf: &[u8], addr: &A)
+                                            -> io::Result<usize> {
+        let addr = try!(addr.to_sock_addr());
+        ext::send_to(self.as_sock(), buf, &addr)
+    }
+
+    fn recv_from_addr(&self, buf: &mut [u8]) -> io::Result<(usize, SockAddr)> {
+        ext::recv_from(self.as_sock(), buf)
+    }
+}
+
+/// Extension methods for passing open file descriptors over Unix domain
+/// sockets with `SCM_RIGHTS` control messages.
+///
+/// The receiving process gets new descriptors referring to the same open
+/// files or sockets as the sender's, so for example a `TcpListener` created by
+/// a `TcpBuilder` in a privileged process can be handed to an unprivileged
+/// one. Data sent along with the descriptors must not be empty for stream
+/// sockets.
+pub trait UnixFdPassingExt {
+    /// Sends `buf` on this socket along with the given file descriptors,
+    /// returning the number of bytes written.
+    ///
+    /// The descriptors are duplicated into the receiving process, so they
+    /// remain open and owned by the caller here. Datagram sockets must be
+    /// connected.
+    fn send_fds(&self, buf: &[u8], fds: &[RawFd]) -> io::Result<usize>;
+
+    /// Receives data into `buf` along with at most `max_fds` file
+    /// descriptors, returning the number of bytes read and the descriptors.
+    ///
+    /// The received descriptors are close-on-exec and are closed when the
+    /// returned `ReceivedFd` handles are dropped. They can be converted into
+    /// the standard library types by passing the result of `into_raw_fd` to
+    /// `FromRawFd`, for example `TcpListener::from_raw_fd`. If more than
+    /// `max_fds` descriptors were sent with the message, the ones which were
+    /// received are closed and an error is returned.
+    fn recv_fds(&self, buf: &mut [u8], max_fds: usize)
+                -> io::Result<(usize, Vec<ReceivedFd>)>;
+}
+
+/// A file descriptor received with `UnixFdPassingExt::recv_fds`, which is
+/// closed when dropped.
+#[derive(Debug)]
+pub struct ReceivedFd {
+    fd: RawFd,
+}
+
+impl AsRawFd for ReceivedFd {
+    fn as_raw_fd(&self) -> RawFd { self.fd }
+}
+
+impl FromRawFd for ReceivedFd {
+    unsafe fn from_raw_fd(fd: RawFd) -> ReceivedFd {
+        ReceivedFd { fd: fd }
+    }
+}
+
+impl IntoRawFd for ReceivedFd {
+    fn into_raw_fd(self) -> RawFd {
+        let fd = self.fd;
+        mem::forget(self);
+        fd
+    }
+}
+
+impl Drop for ReceivedFd {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = libc::close(self.fd);
+        }
+    }
+}
+
+impl UnixFdPassingExt for UnixStream {
+    fn send_fds(&self, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+        send_fds(self.as_raw_fd(), buf, fds)
+    }
+
+    fn recv_fds(&self, buf: &mut [u8], max_fds: usize)
+                -> io::Result<(usize, Vec<ReceivedFd>)> {
+        recv_fds(self.as_raw_fd(), buf, max_fds)
+    }
+}
+
+impl UnixFdPassingExt for UnixDatagram {
+    fn send_fds(&self, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+        send_fds(self.as_raw_fd(), buf, fds)
+    }
+
+    fn recv_fds(&self, buf: &mut [u8], max_fds: usize)
+                -> io::Result<(usize, Vec<ReceivedFd>)> {
+        recv_fds(self.as_raw_fd(), buf, max_fds)
+    }
+}
+
+impl UnixFdPassingExt for UnixSeqpacket {
+    fn send_fds(&self, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+        send_fds(self.as_raw_fd(), buf, fds)
+    }
+
+    fn recv_fds(&self, buf: &mut [u8], max_fds: usize)
+                -> io::Result<(usize, Vec<ReceivedFd>)> {
+        recv_fds(self.as_raw_fd(), buf, max_fds)
+    }
+}
+
+fn send_fds(sock: c_int, buf: &[u8], fds: &[RawFd]) -> io::Result<usize> {
+    let data = unsafe {
+        slice::from_raw_parts(fds.as_ptr() as *const u8, mem::size_of_val(fds))
+    };
+    let mut control = msg::Control::with_space(msg::cmsg_space(data.len()));
+    if !fds.is_empty() {
+        control.push(libc::SOL_SOCKET, msg::SCM_RIGHTS, data);
+    }
+    msg::send(sock, buf, &control, MSG_NOSIGNAL)
+}
+
+fn recv_fds(sock: c_int, buf: &mut [u8], max_fds: usize)
+            -> io::Result<(usize, Vec<ReceivedFd>)> {
+    let space = msg::cmsg_space(max_fds * mem::size_of::<RawFd>());
+    let mut control = msg::Control::with_space(space);
+    let (n, _) = try!(msg::recv(sock, buf, &mut control, 0));
+    let mut fds = Vec::new();
+    for (level, ty, data) in control.iter() {
+        if level == libc::SOL_SOCKET && ty == msg::SCM_RIGHTS {
+            fds.extend(msg::fds(data).into_iter().map(|fd| unsafe {
+                ReceivedFd::from_raw_fd(fd)
+            }));
+        }
+    }
+    Ok((n, fds))
+}
+
+/// The credentials of a process, as exchanged over Unix domain sockets.
+///
+/// This corresponds to the `ucred` structure used by `SO_PEERCRED` and
+/// `SCM_CREDENTIALS` on Linux.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Credentials {
+    /// The process id.
+    pub pid: i32,
+    /// The user id.
+    pub uid: u32,
+    /// The group id.
+    pub gid: u32,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ucred {
+    pid: libc::pid_t,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+impl Credentials {
+    /// Returns the credentials of the calling process, using its real user
+    /// and group ids.
+    ///
+    /// Unprivileged processes can only send these credentials with
+    /// `send_credentials`; the kernel rejects any others.
+    pub fn current() -> Credentials {
+        unsafe {
+            Credentials {
+                pid: libc::getpid() as i32,
+                uid: libc::getuid() as u32,
+                gid: libc::getgid() as u32,
+            }
+        }
+    }
+
+    fn from_raw(raw: ucred) -> Credentials {
+        Credentials { pid: raw.pid, uid: raw.uid, gid: raw.gid }
+    }
+
+    fn to_raw(self) -> ucred {
+        ucred {
+            pid: self.pid as libc::pid_t,
+            uid: self.uid as libc::uid_t,
+            gid: self.gid as libc::gid_t,
+        }
+    }
+}
+
+/// Extension methods for exchanging process credentials over Unix domain
+/// sockets.
+///
+/// These are only available on Linux, through the `SO_PEERCRED`,
+/// `SO_PASSCRED` and `SCM_CREDENTIALS` socket options. Credentials
+/// reported by the kernel are verified, so they can be used for
+/// authorization.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub trait UnixCredentialsExt {
+    /// Get the value of the `SO_PEERCRED` option on this socket.
+    ///
+    /// This returns the credentials of the peer process at the time the
+    /// connection was established, or the socket pair was created.
+    fn peer_credentials(&self) -> io::Result<Credentials>;
+
+    /// Sets the value of the `SO_PASSCRED` option on this socket.
+    ///
+    /// When enabled, every message received on this socket carries the
+    /// credentials of its sender, which can be read with
+    /// `recv_credentials`. This must be enabled on the receiving socket
+    /// before the message is sent.
+    fn set_pass_credentials(&self, pass: bool) -> io::Result<()>;
+
+    /// Gets the value of the `SO_PASSCRED` option on this socket.
+    ///
+    /// For more information about this option, see
+    /// [`set_pass_credentials`][link].
+    ///
+    /// [link]: #tymethod.set_pass_credentials
+    fn pass_credentials(&self) -> io::Result<bool>;
+
+    /// Sends `buf` on this socket along with an `SCM_CREDENTIALS` control
+    /// message carrying `creds`, returning the number of bytes written.
+    ///
+    /// Unless the caller is privileged, `creds` must be the caller's own
+    /// credentials, such as those from `Credentials::current`. Datagram
+    /// sockets must be connected.
+    fn send_credentials(&self, buf: &[u8], creds: &Credentials)
+                        -> io::Result<usize>;
+
+    /// Receives data into `buf`, returning the number of bytes read and the
+    /// credentials of the sender.
+    ///
+    /// Credentials are only available if `SO_PASSCRED` was enabled on this
+    /// socket when the message was sent; otherwise `None` is returned. Any
+    /// file descriptors sent with the message are closed.
+    fn recv_credentials(&self, buf: &mut [u8])
+                        -> io::Result<(usize, Option<Credentials>)>;
+}
+
+macro_rules! credentials_ext {
+    ($name:ident) => (
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        impl UnixCredentialsExt for $name {
+            fn peer_credentials(&self) -> io::Result<Credentials> {
+                ext::getopt(self.as_raw_fd(), libc::SOL_SOCKET, SO_PEERCRED)
+                    .map(Credentials::from_raw)
+            }
+
+            fn set_pass_credentials(&self, pass: bool) -> io::Result<()> {
+                ext::setopt(self.as_raw_fd(), libc::SOL_SOCKET, SO_PASSCRED,
+                            pass as c_int)
+            }
+
+            fn pass_credentials(&self) -> io::Result<bool> {
+                ext::getopt::<c_int>(self.as_raw_fd(), libc::SOL_SOCKET,
+                                     SO_PASSCRED).map(|b| b != 0)
+            }
+
+            fn send_credentials(&self, buf: &[u8], creds: &Credentials)
+                                -> io::Result<usize> {
+                send_credentials(self.as_raw_fd(), buf, creds)
+            }
+
+            fn recv_credentials(&self, buf: &mut [u8])
+                                -> io::Result<(usize, Option<Credentials>)> {
+                recv_credentials(self.as_raw_fd(), buf)
+            }
+        }
+    )
+}
+
+credentials_ext!(UnixStream);
+credentials_ext!(UnixDatagram);
+credentials_ext!(UnixSeqpacket);
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn send_credentials(sock: c_int, buf: &[u8], creds: &Credentials)
+                    -> io::Result<usize> {
+    let raw = creds.to_raw();
+    let data = unsafe {
+        slice::from_raw_parts(&raw as *const _ as *const u8, mem::size_of_val(&raw))
+    };
+    let mut control = msg::Control::with_space(msg::cmsg_space(data.len()));
+    control.push(libc::SOL_SOCKET, SCM_CREDENTIALS, data);
+    msg::send(sock, buf, &control, MSG_NOSIGNAL)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn recv_credentials(sock: c_int, buf: &mut [u8])
+                    -> io::Result<(usize, Option<Credentials>)> {
+    let space = msg::cmsg_space(mem::size_of::<ucred>());
+    let mut control = msg::Control::with_space(space);
+    let (n, _) = try!(msg::recv(sock, buf, &mut control, 0));
+    let creds = control.iter().filter(|&(level, ty, data)| {
+        level == libc::SOL_SOCKET && ty == SCM_CREDENTIALS &&
+            data.len() >= mem::size_of::<ucred>()
+    }).map(|(_, _, data)| unsafe {
+        Credentials::from_raw(ptr::read_unaligned(data.as_ptr() as *const ucred))
+    }).next();
+    // Descriptors the peer sent along with the message are received
+    // close-on-exec by `msg::recv`, and are closed here rather than leaked
+    control.close_fds();
+    Ok((n, creds))
+}
+
+fn local_unix_addr(sock: c_int) -> io::Result<UnixAddr> {
+    unix_addr(|addr, len| unsafe { libc::getsockname(sock, addr, len) })
+}
+
+fn peer_unix_addr(sock: c_int) -> io::Result<UnixAddr> {
+    unix_addr(|addr, len| unsafe { libc::getpeername(sock, addr, len) })
+}
+
+fn unix_addr<F>(f: F) -> io::Result<UnixAddr>
+    where F: FnOnce(*mut libc::sockaddr, *mut socklen_t) -> c_int
+{
+    let addr = try!(sockaddr::with_storage(|addr, len| {
+        ::cvt(f(addr, len)).map(|_| ())
+    }));
+    Ok(addr.as_unix().unwrap_or(UnixAddr::Unnamed))
 }
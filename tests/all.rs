@@ -1,10 +1,11 @@
+extern crate libc;
 extern crate net2;
 
 use std::net::TcpStream;
 use std::io::prelude::*;
 use std::thread;
 
-use net2::TcpBuilder;
+use net2::{TcpBuilder, SocketBuilder};
 
 macro_rules! t {
     ($e:expr) => (match $e {
@@ -32,3 +33,920 @@ fn smoke_build_listener() {
     t!(stream.write(&[1,2,3]));
     t.join().unwrap();
 }
+
+#[test]
+fn smoke_socket_builder() {
+    let b = t!(SocketBuilder::new(libc::AF_INET, libc::SOCK_STREAM,
+                                  libc::IPPROTO_TCP));
+    t!(t!(b.reuse_address(true)).bind("127.0.0.1:0"));
+    let listener = t!(b.listen(200));
+    assert!(b.to_tcp_stream().is_err());
+    #[cfg(unix)]
+    {
+        assert!(b.as_raw_fd_opt().is_none());
+        assert!(b.into_socket().is_err());
+
+        let b = t!(SocketBuilder::new(libc::AF_INET, libc::SOCK_STREAM, 0));
+        let fd = b.as_raw_fd_opt().unwrap();
+        assert_eq!(t!(b.into_socket()), fd);
+        assert_eq!(unsafe { libc::close(fd) }, 0);
+    }
+
+    let addr = t!(listener.local_addr());
+
+    let t = thread::spawn(move || {
+        let mut s = t!(listener.accept()).0;
+        let mut b = [0; 4];
+        t!(s.read(&mut b));
+        assert_eq!(b, [1, 2, 3, 0]);
+    });
+
+    let b = t!(SocketBuilder::new(libc::AF_INET, libc::SOCK_STREAM,
+                                  libc::IPPROTO_TCP));
+    let mut stream = t!(b.connect(&addr));
+    t!(stream.write(&[1,2,3]));
+    t.join().unwrap();
+}
+
+#[test]
+fn sock_addr_round_trip() {
+    use std::net::{SocketAddr, UdpSocket};
+    use net2::{SockAddr, UdpBuilder, UdpSocketExt};
+
+    let v4: SocketAddr = "127.0.0.1:8080".parse().unwrap();
+    let v6: SocketAddr = "[::1]:8080".parse().unwrap();
+    assert_eq!(SockAddr::from(v4).as_socket_addr(), Some(v4));
+    assert_eq!(SockAddr::from(v6).as_socket_addr(), Some(v6));
+    assert_eq!(SockAddr::from(v4).family(), libc::AF_INET);
+
+    // Padding in the raw address is not part of the comparison
+    let mut raw: libc::sockaddr_in = unsafe { std::mem::zeroed() };
+    let len = SockAddr::from(v4).len();
+    unsafe {
+        std::ptr::copy_nonoverlapping(SockAddr::from(v4).as_ptr() as *const u8,
+                                      &mut raw as *mut _ as *mut u8,
+                                      len as usize);
+    }
+    raw.sin_zero = [0xff; 8];
+    let padded = unsafe {
+        SockAddr::from_raw_parts(&raw as *const _ as *const _, len)
+    };
+    assert!(padded == SockAddr::from(v4));
+    assert!(SockAddr::from(v4) != SockAddr::from(v6));
+
+    let any: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let a = t!(t!(UdpBuilder::new_v4()).bind_addr(&SockAddr::from(any)));
+    let b = t!(UdpSocket::bind("127.0.0.1:0"));
+    let to = t!(b.local_addr());
+    assert_eq!(t!(a.send_to_addr(&[1, 2, 3], &to)), 3);
+    let mut buf = [0; 4];
+    let (n, from) = t!(b.recv_from_addr(&mut buf));
+    assert_eq!(n, 3);
+    assert_eq!(from.as_socket_addr(), Some(t!(a.local_addr())));
+    assert!(from == SockAddr::from(t!(a.local_addr())));
+
+    t!(b.connect_addr(&SockAddr::from(t!(a.local_addr()))));
+    assert_eq!(t!(b.peer_addr()), t!(a.local_addr()));
+}
+
+#[cfg(unix)]
+#[test]
+fn sock_addr_unix() {
+    use std::env;
+    use std::fs;
+    use std::net::SocketAddr;
+    use net2::{SockAddr, UdpSocketExt};
+
+    let paths = ["server", "client"].iter().map(|name| {
+        let path = env::temp_dir().join(format!("net2-sockaddr-{}-{}.sock",
+                                                name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }).collect::<Vec<_>>();
+    let server_addr = t!(SockAddr::unix(&paths[0]));
+    let client_addr = t!(SockAddr::unix(&paths[1]));
+    assert_eq!(server_addr.family(), libc::AF_UNIX);
+
+    let b = t!(SocketBuilder::new(libc::AF_UNIX, libc::SOCK_DGRAM, 0));
+    let server = t!(t!(b.bind_addr(&server_addr)).to_udp_socket());
+    let b = t!(SocketBuilder::new(libc::AF_UNIX, libc::SOCK_DGRAM, 0));
+    let client = t!(t!(b.bind_addr(&client_addr)).to_udp_socket());
+    t!(client.connect_addr(&server_addr));
+    assert_eq!(t!(client.send(&[1, 2, 3])), 3);
+
+    let mut buf = [0; 4];
+    let (n, from) = t!(server.recv_from_addr(&mut buf));
+    assert_eq!(n, 3);
+    assert!(from == client_addr);
+    assert_eq!(t!(server.send_to_addr(&[4], &from)), 1);
+    assert_eq!(t!(client.recv(&mut buf)), 1);
+
+    assert_eq!(from.as_socket_addr(), None);
+    assert!(from != server_addr);
+    assert!(from != SockAddr::from("127.0.0.1:0".parse::<SocketAddr>().unwrap()));
+    for path in paths {
+        t!(fs::remove_file(&path));
+    }
+}
+
+#[test]
+fn connect_timeout() {
+    let listener = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(1));
+    let addr = t!(listener.local_addr());
+    let s = t!(t!(TcpBuilder::new_v4()).connect_timeout_ms(&addr, 1000));
+    assert_eq!(t!(s.peer_addr()), addr);
+
+    drop(listener);
+    let err = t!(TcpBuilder::new_v4()).connect_timeout_ms(&addr, 1000)
+                                      .err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+}
+
+#[cfg(feature = "nightly")]
+#[test]
+fn connect_timeout_duration() {
+    use std::time::Duration;
+
+    let listener = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(1));
+    let addr = t!(listener.local_addr());
+    let timeout = Duration::from_millis(1000);
+    let s = t!(t!(TcpBuilder::new_v4()).connect_timeout(&addr, timeout));
+    assert_eq!(t!(s.peer_addr()), addr);
+
+    drop(listener);
+    let err = t!(TcpBuilder::new_v4()).connect_timeout(&addr, timeout)
+                                      .err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+}
+
+#[test]
+fn structured_errors() {
+    use net2::Error;
+
+    let b = t!(TcpBuilder::new_v4());
+    let err = b.only_v6(true).err().unwrap();
+    match *err.get_ref().unwrap().downcast_ref::<Error>().unwrap() {
+        Error::Os { ref op, ref error } => {
+            assert_eq!(op, "setsockopt(IPPROTO_IPV6, IPV6_V6ONLY)");
+            assert_eq!(error.kind(), err.kind());
+            assert_eq!(Error::raw_os_error(&err), error.raw_os_error());
+            assert!(error.raw_os_error().is_some());
+        }
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+
+    t!(b.to_tcp_stream());
+    let err = b.to_tcp_stream().err().unwrap();
+    match *err.get_ref().unwrap().downcast_ref::<Error>().unwrap() {
+        Error::SocketConsumed => {}
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn template_keeps_protocol() {
+    use std::mem;
+    use std::net::UdpSocket;
+    use std::os::unix::prelude::*;
+    use libc::{c_int, c_void, socklen_t};
+
+    extern {
+        fn getsockopt(fd: c_int, level: c_int, name: c_int, val: *mut c_void,
+                      len: *mut socklen_t) -> c_int;
+    }
+    const IPPROTO_UDPLITE: c_int = 136;
+    const SO_PROTOCOL: c_int = 38;
+
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, IPPROTO_UDPLITE) };
+    if fd < 0 {
+        return // UDP-Lite is not available
+    }
+    let b = unsafe { SocketBuilder::from_raw_fd(fd) };
+    let peer = t!(UdpSocket::bind("127.0.0.1:0"));
+    let _first = t!(b.connect(t!(peer.local_addr())));
+
+    // The second socket is created from the template
+    let second = t!(b.connect_new(t!(peer.local_addr())));
+    let mut protocol: c_int = 0;
+    let mut len = mem::size_of::<c_int>() as socklen_t;
+    assert_eq!(unsafe {
+        getsockopt(second.as_raw_fd(), libc::SOL_SOCKET, SO_PROTOCOL,
+                   &mut protocol as *mut _ as *mut c_void, &mut len)
+    }, 0);
+    assert_eq!(protocol, IPPROTO_UDPLITE);
+}
+
+#[test]
+fn bind_address_selection() {
+    use std::net::SocketAddr;
+    use net2::{AddrSelection, Error};
+
+    let v4: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let v6: SocketAddr = "[::1]:0".parse().unwrap();
+
+    let b = t!(TcpBuilder::new_v4());
+    t!(b.bind(&[v6, v4][..]));
+    let l = t!(b.listen(1));
+    assert_eq!(t!(l.local_addr()).ip(), v4.ip());
+
+    let b = t!(TcpBuilder::new_v4());
+    let other: SocketAddr = "127.0.0.2:0".parse().unwrap();
+    let err = b.bind_with(&[v4, other, v6][..], AddrSelection::PreferV4)
+               .err().unwrap();
+    match *err.get_ref().unwrap().downcast_ref::<Error>().unwrap() {
+        Error::MultipleAddresses(ref addrs) => assert_eq!(*addrs, [v4, other]),
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+    t!(b.bind_with(&[v4, other, v6][..], AddrSelection::First));
+}
+
+#[test]
+fn connect_error_lists_attempts() {
+    use std::net::SocketAddr;
+    use net2::ConnectError;
+
+    let listener = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(1));
+    let addr = t!(listener.local_addr());
+    drop(listener);
+    let addrs = [addr, addr];
+
+    let err = t!(TcpBuilder::new_v4()).connect(&addrs[..]).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::ConnectionRefused);
+    let err = err.get_ref().unwrap().downcast_ref::<ConnectError>().unwrap();
+    assert_eq!(err.attempts().len(), 2);
+    assert_eq!(err.attempts()[0].addr(), addr);
+    assert_eq!(err.attempts()[0].error().kind(),
+               std::io::ErrorKind::ConnectionRefused);
+
+    let none: &[SocketAddr] = &[];
+    let err = t!(TcpBuilder::new_v4()).connect(none).err().unwrap();
+    let err = err.get_ref().unwrap().downcast_ref::<ConnectError>().unwrap();
+    assert!(err.attempts().is_empty());
+}
+
+#[test]
+fn builder_as_template() {
+    use std::net::SocketAddr;
+    use net2::{ConnectError, Error};
+
+    let listener = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(10));
+    let addr = t!(listener.local_addr());
+
+    let b = t!(TcpBuilder::new_v6());
+    t!(b.ttl(42));
+    t!(b.reuse_address(true));
+
+    // The v6 attempt fails, and the v4 one is made on a new socket
+    let addrs = [SocketAddr::new("::1".parse().unwrap(), addr.port()), addr];
+    let s1 = t!(b.connect(&addrs[..]));
+    assert!(b.connect(&addr).is_err());
+    let s2 = t!(b.connect_new(&addr));
+    assert_eq!(t!(s1.ttl()), 42);
+    assert_eq!(t!(s2.ttl()), 42);
+    assert!(t!(s1.local_addr()) != t!(s2.local_addr()));
+
+    // A source address with no IPv6 equivalent fails the IPv6 attempt
+    // rather than being dropped
+    let b = t!(TcpBuilder::new_v4());
+    t!(b.bind("127.0.0.1:0"));
+    let err = b.connect(&addrs[..1]).err().unwrap();
+    let err = err.get_ref().unwrap().downcast_ref::<ConnectError>().unwrap();
+    match *err.attempts()[0].error().get_ref().unwrap()
+                                    .downcast_ref::<Error>().unwrap() {
+        Error::BindFamily => {}
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+}
+
+#[test]
+fn happy_eyeballs() {
+    use std::net::SocketAddr;
+    use net2::HappyEyeballs;
+
+    let listener = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(1));
+    let port = t!(listener.local_addr()).port();
+    let addrs = [
+        SocketAddr::new("::1".parse().unwrap(), port),
+        SocketAddr::new("::1".parse().unwrap(), port),
+        SocketAddr::new("127.0.0.1".parse().unwrap(), port),
+    ];
+
+    let mut attempts = 0;
+    let s = t!(HappyEyeballs::new().delay_ms(50).connect(&addrs[..], |b| {
+        attempts += 1;
+        b.reuse_address(true).map(|_| ())
+    }));
+    assert_eq!(t!(s.peer_addr()), addrs[2]);
+    assert!(attempts >= 2);
+
+    drop(listener);
+    assert!(HappyEyeballs::new().connect(&addrs[..], |_| Ok(())).is_err());
+}
+
+#[test]
+fn happy_eyeballs_sync_failure() {
+    use std::io;
+    use std::time::{Duration, Instant};
+    use net2::HappyEyeballs;
+
+    // A listener whose accept queue is full leaves new attempts pending
+    let full = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(0));
+    let full_addr = t!(full.local_addr());
+    let _queued = (0..4).filter_map(|_| {
+        TcpStream::connect_timeout(&full_addr, Duration::from_millis(100)).ok()
+    }).collect::<Vec<_>>();
+    let listener = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(1));
+    let addrs = [full_addr, full_addr, t!(listener.local_addr())];
+
+    // The second attempt fails before connecting, so the third should start
+    // straight away rather than a whole delay later
+    let start = Instant::now();
+    let mut attempts = 0;
+    let s = t!(HappyEyeballs::new().delay_ms(1000).connect(&addrs[..], |_| {
+        attempts += 1;
+        if attempts == 2 {
+            Err(io::Error::new(io::ErrorKind::Other, "unreachable"))
+        } else {
+            Ok(())
+        }
+    }));
+    assert_eq!(t!(s.peer_addr()), addrs[2]);
+    assert!(start.elapsed() < Duration::from_millis(1800));
+}
+
+#[test]
+fn listener_set() {
+    use std::net::SocketAddr;
+
+    let b = t!(TcpBuilder::new_v4());
+    t!(b.ttl(42));
+    let addrs: [SocketAddr; 2] = ["127.0.0.1:0".parse().unwrap(),
+                                  "127.0.0.1:0".parse().unwrap()];
+    let set = t!(b.listen_all(&addrs[..], 10));
+    assert_eq!(set.listeners().len(), 2);
+    for l in set.listeners() {
+        assert_eq!(t!(l.ttl()), 42);
+    }
+
+    let local = t!(set.local_addrs());
+    let _s = t!(TcpStream::connect(&local[1]));
+    let (stream, _, i) = t!(set.accept());
+    assert_eq!(i, 1);
+    assert_eq!(t!(stream.local_addr()), local[1]);
+    let _s = t!(TcpStream::connect(&local[0]));
+    assert_eq!(t!(set.accept()).2, 0);
+
+    // Accepting is nonblocking, but neither the listeners nor the stream are
+    // left that way
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        let nonblocking = |fd| unsafe {
+            libc::fcntl(fd, libc::F_GETFL) & libc::O_NONBLOCK != 0
+        };
+        assert!(!nonblocking(stream.as_raw_fd()));
+        for l in set.listeners() {
+            assert!(!nonblocking(l.as_raw_fd()));
+        }
+    }
+
+    // The builder's own socket is left alone
+    t!(b.bind("127.0.0.1:0"));
+}
+
+#[test]
+fn dual_stack() {
+    use std::net::{SocketAddr, UdpSocket};
+    use net2::{TcpListenerExt, UdpBuilder, UdpSocketExt};
+
+    let b = t!(TcpBuilder::new_dual_stack());
+    let listener = t!(t!(b.bind("127.0.0.1:0")).listen(10));
+    let addr = t!(listener.local_addr());
+    assert!(addr.is_ipv6());
+    let v4 = SocketAddr::new("127.0.0.1".parse().unwrap(), addr.port());
+
+    let s = t!(TcpStream::connect(&v4));
+    let (_, peer) = t!(listener.accept_unmapped());
+    assert_eq!(peer, t!(s.local_addr()));
+    let s = t!(t!(TcpBuilder::new_dual_stack()).connect(&v4));
+    assert!(t!(s.peer_addr()).is_ipv6());
+
+    let socket = t!(t!(UdpBuilder::new_dual_stack()).bind("127.0.0.1:0"));
+    let port = t!(socket.local_addr()).port();
+    let other = t!(UdpSocket::bind("127.0.0.1:0"));
+    t!(other.send_to(b"hi", ("127.0.0.1", port)));
+    let mut buf = [0; 2];
+    let (_, from) = t!(socket.recv_from_unmapped(&mut buf));
+    assert_eq!(from, t!(other.local_addr()));
+    t!(socket.send_to_mapped(b"ok", from));
+    assert_eq!(t!(other.recv_from(&mut buf)), (2, SocketAddr::V4(
+        std::net::SocketAddrV4::new("127.0.0.1".parse().unwrap(), port))));
+}
+
+#[test]
+fn udp_builder_options() {
+    use net2::{Error, UdpBuilder};
+
+    let b = t!(UdpBuilder::new_v4());
+    t!(b.ttl(42));
+    let s = t!(b.bind("127.0.0.1:0"));
+    assert_eq!(t!(s.ttl()), 42);
+
+    // Options set on a finished builder are recorded without a socket
+    t!(b.reuse_address(true));
+    let err = b.take_error().err().unwrap();
+    assert!(err.get_ref().unwrap().downcast_ref::<Error>().is_some());
+    assert_eq!(format!("{:?}", b), "UdpBuilder { socket: None }");
+}
+
+#[test]
+fn family_from_address() {
+    use std::net::SocketAddr;
+    #[cfg(unix)] use std::os::unix::io::AsRawFd;
+    use net2::{Error, TcpStreamExt, UdpBuilder};
+
+    let v6: SocketAddr = "[::1]:0".parse().unwrap();
+    let v4: SocketAddr = "127.0.0.1:0".parse().unwrap();
+    let l = t!(t!(t!(TcpBuilder::for_addr(&v4)).bind(&v4)).listen(10));
+    let s = t!(t!(UdpBuilder::for_addr(&v4)).bind(&v4));
+    assert!(t!(s.local_addr()).is_ipv4());
+    assert!(t!(t!(TcpBuilder::for_addr(&v6)).to_tcp_stream()).only_v6().is_ok());
+
+    let b = t!(TcpBuilder::new_deferred());
+    t!(b.ttl(42));
+    t!(b.reuse_address(true));
+    let s = t!(b.connect(t!(l.local_addr())));
+    assert_eq!(t!(s.ttl()), 42);
+    assert!(t!(s.local_addr()).is_ipv4());
+
+    let b = t!(TcpBuilder::new_deferred());
+    t!(b.ttl(42));
+    for err in vec![b.listen(10).err().unwrap(), b.take_error().err().unwrap()] {
+        match *err.get_ref().unwrap().downcast_ref::<Error>().unwrap() {
+            Error::NotCreated => {}
+            ref e => panic!("unexpected error: {:?}", e),
+        }
+    }
+    #[cfg(unix)]
+    assert_eq!(b.as_raw_fd(), -1);
+    let l = t!(t!(b.bind("127.0.0.1:0")).listen(10));
+    assert_eq!(t!(l.ttl()), 42);
+    #[cfg(unix)]
+    assert_eq!(b.as_raw_fd(), -1);
+}
+
+#[test]
+fn bind_port_range() {
+    use std::io::ErrorKind;
+    use std::net::{IpAddr, UdpSocket};
+    use net2::UdpBuilder;
+
+    // The ranges start from ports the kernel just handed out, rather than
+    // fixed ones which may be busy on a shared machine
+    let ip: IpAddr = "127.0.0.1".parse().unwrap();
+    let l = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(1));
+    let p = t!(l.local_addr()).port();
+    let err = t!(TcpBuilder::new_v4()).bind_port_range(ip, p, p).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+
+    let hi = p.saturating_add(9);
+    let b = t!(TcpBuilder::new_v4());
+    let (_, port) = t!(b.bind_port_range(ip, p, hi));
+    assert!(port > p && port <= hi);
+    assert_eq!(t!(t!(b.listen(1)).local_addr()).port(), port);
+    let (_, port) = t!(t!(TcpBuilder::new_v4()).bind_port_range_random(ip, p, hi));
+    assert!(port > p && port <= hi);
+
+    let u = t!(UdpSocket::bind("127.0.0.1:0"));
+    let p = t!(u.local_addr()).port();
+    let hi = p.saturating_add(9);
+    let (s, port) = t!(t!(UdpBuilder::new_v4()).bind_port_range(ip, p, hi));
+    assert!(port > p && port <= hi);
+    assert_eq!(t!(s.local_addr()).port(), port);
+
+    // The top of the range is included, so the last port can be asked for
+    match t!(UdpBuilder::new_v4()).bind_port_range(ip, 65535, 65535) {
+        Ok((_, port)) => assert_eq!(port, 65535),
+        Err(e) => assert_eq!(e.kind(), ErrorKind::AddrInUse),
+    }
+}
+
+#[test]
+fn bind_source_ip() {
+    let l = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(10));
+    let addr = t!(l.local_addr());
+
+    let b = t!(TcpBuilder::new_v4());
+    t!(b.bind_source_ip("127.0.0.1".parse().unwrap()));
+    let s1 = t!(b.connect(&addr));
+    let s2 = t!(b.connect_new(&addr));
+    assert_eq!(t!(s1.local_addr()).ip(), addr.ip());
+    assert!(t!(s1.local_addr()) != t!(s2.local_addr()));
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        use net2::unix::UnixTcpBuilderExt;
+
+        t!(t!(TcpBuilder::new_v4()).bind_address_no_port(true));
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn bind_device() {
+    use std::io::{self, ErrorKind};
+    use net2::{Error, UdpBuilder};
+    use net2::unix::{UnixIpSocketExt, UnixTcpBuilderExt, UnixUdpBuilderExt};
+
+    // Without CAP_NET_RAW, binding (before Linux 5.7) or unbinding fails
+    // with an error naming the option
+    fn denied<T>(res: io::Result<T>) -> Option<T> {
+        match res {
+            Ok(t) => Some(t),
+            Err(ref e) if e.kind() == ErrorKind::PermissionDenied => {
+                let msg = e.to_string();
+                assert!(msg.starts_with("setsockopt(SOL_SOCKET, SO_BINDTO"), "{}", msg);
+                assert_eq!(Error::raw_os_error(e), Some(libc::EPERM));
+                None
+            }
+            Err(e) => panic!("bind_device failed with: {}", e),
+        }
+    }
+
+    let b = t!(TcpBuilder::new_v4());
+    if denied(b.bind_device(Some(b"lo"))).is_none() {
+        return
+    }
+    let l = t!(t!(b.bind("127.0.0.1:0")).listen(1));
+    assert_eq!(t!(l.bind_device()), Some(b"lo".to_vec()));
+    let index = t!(l.bind_device_index()).unwrap();
+
+    let s = t!(t!(t!(UdpBuilder::new_v4()).bind_device_index(Some(index)))
+                   .bind("127.0.0.1:0"));
+    assert_eq!(t!(s.bind_device()), Some(b"lo".to_vec()));
+    if denied(s.set_bind_device(None)).is_none() {
+        return
+    }
+    assert_eq!(t!(s.bind_device()), None);
+    assert_eq!(t!(s.bind_device_index()), None);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn freebind() {
+    use net2::UdpBuilder;
+    use net2::unix::{UnixIpSocketExt, UnixTcpBuilderExt, UnixUdpBuilderExt};
+
+    // Not assigned to any interface, so only bindable with IP_FREEBIND
+    // (unless the host allows non-local binds anyway)
+    let addr = "192.0.2.1:0";
+    let b = t!(TcpBuilder::new_v4());
+    t!(b.freebind(true));
+    let l = t!(t!(b.bind(addr)).listen(1));
+    assert_eq!(t!(l.local_addr()).to_string(),
+               format!("192.0.2.1:{}", t!(l.local_addr()).port()));
+    assert!(t!(l.freebind()));
+
+    let s = t!(t!(t!(UdpBuilder::new_v4()).freebind(true)).bind(addr));
+    assert!(t!(s.freebind()));
+
+    let b = t!(TcpBuilder::new_v6());
+    t!(b.freebind_v6(true));
+    let l = t!(t!(b.bind("[2001:db8::1]:0")).listen(1));
+    assert!(t!(l.freebind_v6()));
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn transparent_proxy() {
+    use std::io::ErrorKind;
+    use std::net::UdpSocket;
+    use net2::UdpBuilder;
+    use net2::unix::{UnixIpSocketExt, UnixTcpBuilderExt, UnixTcpStreamExt,
+                     UnixUdpSocketExt};
+
+    let b = t!(TcpBuilder::new_v4());
+    match b.transparent(true) {
+        Ok(_) => assert!(t!(t!(t!(b.bind("127.0.0.1:0")).listen(1)).transparent())),
+        Err(e) => assert_eq!(e.kind(), ErrorKind::PermissionDenied),
+    }
+
+    // Without a NAT redirect there is no original destination
+    let l = t!(t!(t!(TcpBuilder::new_v4()).bind("127.0.0.1:0")).listen(1));
+    let s = t!(TcpStream::connect(t!(l.local_addr())));
+    assert!(s.original_dst().is_err());
+
+    let s = t!(t!(UdpBuilder::new_v4()).bind("127.0.0.1:0"));
+    assert!(!t!(s.recv_original_dst()));
+    t!(s.set_recv_original_dst(true));
+    assert!(t!(s.recv_original_dst()));
+
+    let c = t!(UdpSocket::bind("127.0.0.1:0"));
+    t!(c.send_to(b"hello", t!(s.local_addr())));
+    let mut buf = [0; 16];
+    let (n, from, dst) = t!(s.recv_from_original_dst(&mut buf));
+    assert_eq!(&buf[..n], b"hello");
+    assert_eq!(from, t!(c.local_addr()));
+    assert_eq!(dst, Some(t!(s.local_addr())));
+
+    t!(s.set_recv_original_dst(false));
+    t!(c.send_to(b"hello", t!(s.local_addr())));
+    assert_eq!(t!(s.recv_from_original_dst(&mut buf)).2, None);
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn reuse_port_pool() {
+    use net2::ListenerSet;
+    use net2::unix::ReusePortPool;
+
+    let b = t!(TcpBuilder::new_v4());
+    t!(b.reuse_address(true));
+    let mut pool = t!(ReusePortPool::new(&b, "127.0.0.1:0", 3, 16));
+    let addr = pool.local_addr();
+    assert!(addr.port() != 0);
+    assert_eq!(pool.len(), 3);
+    t!(pool.add());
+    assert_eq!(pool.len(), 4);
+    for l in pool.listeners() {
+        assert_eq!(t!(l.local_addr()), addr);
+    }
+
+    // A worker blocked accepting on a removed member is woken with an error
+    let l = t!(pool.listeners()[0].try_clone());
+    let worker = thread::spawn(move || l.accept().is_err());
+    thread::sleep(std::time::Duration::from_millis(50));
+    t!(pool.remove(0));
+    assert!(worker.join().unwrap());
+    assert_eq!(pool.len(), 3);
+
+    let set = pool.into_iter().collect::<ListenerSet>();
+    for _ in 0..8 {
+        let _s = t!(TcpStream::connect(addr));
+        t!(set.accept());
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn cloexec_and_accept4() {
+    use std::os::unix::prelude::*;
+    use net2::TcpListenerExt;
+    use net2::unix::SOCK_NONBLOCK;
+
+    fn flags(fd: RawFd) -> (bool, bool) {
+        unsafe {
+            // FD_CLOEXEC is 1 on every platform
+            let fd_flags = libc::fcntl(fd, libc::F_GETFD);
+            let fl_flags = libc::fcntl(fd, libc::F_GETFL);
+            (fd_flags & 1 != 0, fl_flags & libc::O_NONBLOCK != 0)
+        }
+    }
+
+    let b = t!(SocketBuilder::new(libc::AF_INET,
+                                  libc::SOCK_STREAM | SOCK_NONBLOCK, 0));
+    t!(b.bind("127.0.0.1:0"));
+    let listener = t!(b.listen(10));
+    assert_eq!(flags(listener.as_raw_fd()), (true, true));
+    t!(listener.set_nonblocking(false));
+
+    let addr = t!(listener.local_addr());
+    let _a = t!(TcpStream::connect(&addr));
+    let (s, _) = t!(listener.accept4(true));
+    assert_eq!(flags(s.as_raw_fd()), (true, true));
+    let c = t!(TcpStream::connect(&addr));
+    let (s, peer) = t!(listener.accept4(false));
+    assert_eq!(flags(s.as_raw_fd()), (true, false));
+    assert_eq!(peer, t!(c.local_addr()));
+}
+
+#[cfg(unix)]
+#[test]
+fn smoke_unix_stream_builders() {
+    use std::env;
+    use std::fs;
+    use net2::unix::{UnixListenerBuilder, UnixStreamBuilder};
+
+    let path = env::temp_dir().join(format!("net2-smoke-{}.sock",
+                                            std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let b = t!(UnixListenerBuilder::new());
+    t!(t!(b.recv_buffer_size(4096)).bind(&path));
+    let listener = t!(b.listen(10));
+    assert!(b.to_unix_listener().is_err());
+    assert_eq!(format!("{:?}", b), "UnixListenerBuilder { socket: None }");
+    assert!(b.as_raw_fd_opt().is_none());
+    assert!(b.send_buffer_size(4096).is_err());
+    assert!(b.local_addr().is_err());
+    assert!(b.take_error().is_err());
+
+    let t = thread::spawn(move || {
+        let mut s = t!(listener.accept()).0;
+        let mut b = [0; 4];
+        t!(s.read(&mut b));
+        assert_eq!(b, [1, 2, 3, 0]);
+    });
+
+    let b = t!(UnixStreamBuilder::new());
+    t!(b.write_timeout_ms(Some(1000)));
+    let mut stream = t!(b.connect(&path));
+    assert_eq!(format!("{:?}", b), "UnixStreamBuilder { socket: None }");
+    t!(stream.write(&[1,2,3]));
+    t.join().unwrap();
+    t!(fs::remove_file(&path));
+}
+
+#[cfg(unix)]
+#[test]
+fn smoke_unix_datagram_builder() {
+    use std::env;
+    use std::fs;
+    use net2::unix::UnixDatagramBuilder;
+
+    let path = env::temp_dir().join(format!("net2-dgram-{}.sock",
+                                            std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let b = t!(UnixDatagramBuilder::new());
+    t!(b.recv_buffer_size(65536));
+    let receiver = t!(b.bind(&path));
+    assert!(b.to_unix_datagram().is_err());
+
+    let b = t!(UnixDatagramBuilder::new());
+    t!(b.send_buffer_size(65536));
+    let sender = t!(b.to_unix_datagram());
+    t!(sender.send_to(&[1, 2, 3], &path));
+
+    let mut buf = [0; 4];
+    assert_eq!(t!(receiver.recv(&mut buf)), 3);
+    assert_eq!(buf, [1, 2, 3, 0]);
+    t!(fs::remove_file(&path));
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn smoke_unix_abstract_and_autobind() {
+    use net2::unix::{UnixAddr, UnixDatagramBuilder, UnixListenerBuilder,
+                     UnixSocketExt, UnixStreamBuilder};
+
+    let name = format!("net2-abstract-{}", std::process::id());
+    let addr = UnixAddr::abstract_name(&name);
+
+    let b = t!(UnixListenerBuilder::new());
+    t!(b.bind_addr(&addr));
+    assert_eq!(t!(b.local_addr()), addr);
+    let listener = t!(b.listen(10));
+    assert_eq!(t!(listener.local_unix_addr()), addr);
+
+    let b = t!(UnixStreamBuilder::new());
+    let _stream = t!(b.connect_addr(&addr));
+    t!(listener.accept());
+
+    let b = t!(UnixDatagramBuilder::new());
+    assert_eq!(t!(b.local_addr()), UnixAddr::Unnamed);
+    t!(b.autobind());
+    let socket = t!(b.to_unix_datagram());
+    match t!(socket.local_unix_addr()) {
+        UnixAddr::Abstract(ref name) => assert!(!name.is_empty()),
+        other => panic!("not autobound: {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn smoke_unix_seqpacket() {
+    use std::env;
+    use std::fs;
+    use net2::unix::{UnixSeqpacketBuilder, UnixSeqpacketListenerBuilder};
+
+    let path = env::temp_dir().join(format!("net2-seqpacket-{}.sock",
+                                            std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let b = t!(UnixSeqpacketListenerBuilder::new());
+    t!(b.bind(&path));
+    let listener = t!(b.listen(10));
+
+    let t = thread::spawn(move || {
+        let s = t!(listener.accept()).0;
+        let mut b = [0; 8];
+        assert_eq!(t!(s.recv(&mut b)), 3);
+        assert_eq!(&b[..3], &[1, 2, 3]);
+        assert_eq!(t!(s.recv(&mut b)), 2);
+        assert_eq!(&b[..2], &[4, 5]);
+        assert_eq!(t!(s.recv(&mut b)), 0);
+    });
+
+    let b = t!(UnixSeqpacketBuilder::new());
+    let stream = t!(b.connect(&path));
+    t!(stream.send(&[1, 2, 3]));
+    t!(stream.send(&[4, 5]));
+    drop(stream);
+    t.join().unwrap();
+    t!(fs::remove_file(&path));
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_listener_file_lifecycle() {
+    use std::env;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use net2::unix::UnixListenerBuilder;
+
+    let path = env::temp_dir().join(format!("net2-lifecycle-{}.sock",
+                                            std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    // leave a stale socket file behind
+    let b = t!(UnixListenerBuilder::new());
+    t!(b.bind(&path));
+    drop(b);
+    assert!(t!(UnixListenerBuilder::new()).bind(&path).is_err());
+
+    let b = t!(UnixListenerBuilder::new());
+    t!(t!(b.remove_stale(true)).mode(0o660));
+    t!(b.bind(&path));
+    // The mode is exact rather than masked by the umask, and is fixed by bind
+    let mode = t!(fs::metadata(&path)).permissions().mode();
+    assert_eq!(mode & 0o777, 0o660);
+    assert!(b.mode(0o600).is_err());
+    assert!(b.owner(None, None).is_err());
+    let listener = t!(b.listen_guarded(10));
+
+    // a live socket is not removed
+    let b = t!(UnixListenerBuilder::new());
+    t!(b.remove_stale(true));
+    assert!(b.bind(&path).is_err());
+    assert!(fs::metadata(&path).is_ok());
+
+    drop(listener);
+    assert!(fs::metadata(&path).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_pass_fds() {
+    use std::net::TcpListener;
+    use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+    use std::os::unix::net::UnixStream;
+    use net2::Error;
+    use net2::unix::UnixFdPassingExt;
+
+    let (a, b) = t!(UnixStream::pair());
+    let listener = t!(TcpListener::bind("127.0.0.1:0"));
+    let addr = t!(listener.local_addr());
+
+    assert_eq!(t!(a.send_fds(&[1], &[listener.as_raw_fd()])), 1);
+    let mut buf = [0; 4];
+    let (n, mut fds) = t!(b.recv_fds(&mut buf, 4));
+    assert_eq!(n, 1);
+    assert_eq!(fds.len(), 1);
+    assert!(fds[0].as_raw_fd() != listener.as_raw_fd());
+
+    let received = unsafe { TcpListener::from_raw_fd(fds.remove(0).into_raw_fd()) };
+    assert_eq!(t!(received.local_addr()), addr);
+
+    // Too many descriptors for the buffer is an error rather than a loss
+    let fd = listener.as_raw_fd();
+    t!(a.send_fds(&[1], &[fd, fd, fd]));
+    let err = b.recv_fds(&mut buf, 1).err().unwrap();
+    match *err.get_ref().unwrap().downcast_ref::<Error>().unwrap() {
+        Error::ControlTruncated => {}
+        ref e => panic!("unexpected error: {:?}", e),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[test]
+fn unix_credentials() {
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::{UnixDatagram, UnixStream};
+    use net2::unix::{Credentials, UnixCredentialsExt, UnixFdPassingExt};
+
+    let (a, _b) = t!(UnixStream::pair());
+    assert_eq!(t!(a.peer_credentials()), Credentials::current());
+
+    let (a, b) = t!(UnixDatagram::pair());
+    t!(b.set_pass_credentials(true));
+    assert!(t!(b.pass_credentials()));
+    t!(a.send_credentials(&[1, 2], &Credentials::current()));
+    let mut buf = [0; 4];
+    let (n, creds) = t!(b.recv_credentials(&mut buf));
+    assert_eq!(n, 2);
+    assert_eq!(creds, Some(Credentials::current()));
+
+    // A descriptor sent along with the data is closed, not leaked, so the
+    // other end of the smuggled pair sees end-of-file
+    let (a, b) = t!(UnixDatagram::pair());
+    let (c, mut d) = t!(UnixStream::pair());
+    t!(a.send_fds(&[1], &[c.as_raw_fd()]));
+    drop(c);
+    let (n, creds) = t!(b.recv_credentials(&mut buf));
+    assert_eq!(n, 1);
+    assert_eq!(creds, None);
+    t!(d.set_nonblocking(true));
+    assert_eq!(t!(d.read(&mut buf)), 0);
+}